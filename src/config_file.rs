@@ -1,5 +1,21 @@
-use serde::Deserialize;
-use std::fs;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use serde_json::Value;
+
+use crate::input_format::InputFormat;
+use crate::jq::Jq;
+use crate::prom_metric::MetricType;
+use crate::prom_name::{self, NameSanitization};
+use crate::http_client;
+use crate::value_parser::ValueParser;
+
+/// Name of the module a config file without a `modules:` section gets loaded as
+pub const DEFAULT_MODULE: &str = "default";
+
+/// States a state set discovers per metric, further unknown values are reported as `other`
+pub const MAX_DISCOVERED_STATES: usize = 16;
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct GlobalLabel {
@@ -7,53 +23,951 @@ pub struct GlobalLabel {
     pub selector: String
 }
 
+/// Where the label value of an iterated include element comes from
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "String")]
+pub enum IncludeLabelValue {
+    /// The object key of the element
+    Key,
+    /// The array index of the element
+    Index,
+    /// A `jq` selector evaluated against the element
+    Selector(String)
+}
+
+impl From<String> for IncludeLabelValue {
+    fn from(label_value: String) -> Self {
+        match label_value.as_str() {
+            "key" => IncludeLabelValue::Key,
+            "index" => IncludeLabelValue::Index,
+            _ => IncludeLabelValue::Selector(label_value)
+        }
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct Include {
     pub name: String,
+    #[serde(deserialize_with = "string_or_list")]
     pub selector: Vec<String>,
     pub label_name: String,
-    pub label_selector: String
+    pub label_selector: Option<String>,
+    /// When set, every selector may yield many elements, each one gets converted
+    pub label_value: Option<IncludeLabelValue>,
+    /// Iterates the objects and arrays the selectors yield, so elements are known by their object key
+    pub iterate: Option<bool>,
+    /// Replaces the value mappings of the module for this include
+    pub value_mappings: Option<ValueMappings>,
+    pub value_parser: Option<ValueParser>
+}
+
+/// Allows a single selector to be written without wrapping it into a list
+fn string_or_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>)
+    }
+
+    match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(selector) => Ok(vec!(selector)),
+        StringOrList::List(selectors) => Ok(selectors)
+    }
 }
 
+/// Explicit metric whose value and labels are resolved via `jq` selectors
 #[derive(Deserialize, Clone, Debug)]
-pub struct ConfigFile {
+pub struct MetricMapping {
+    pub name: String,
+    pub value: String,
+    #[serde(rename = "type")]
+    pub metric_type: Option<MetricType>,
+    pub help: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Replaces the value mappings of the module for this metric
+    pub value_mappings: Option<ValueMappings>,
+    pub value_parser: Option<ValueParser>,
+    /// Keys of the objects `histogram` and `summary` metrics are assembled from
+    pub distribution: Option<Distribution>
+}
+
+/// Keys of a JSON object holding a histogram, such as
+/// `{"le_0.1": 12, "le_0.5": 30, "count": 40, "sum": 9.3}`, or a summary, such as `{"p50": 0.2, "p99": 1.4}`
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Distribution {
+    /// Prefix of bucket keys, followed by the upper bound. Defaults to `le_`
+    pub bucket_prefix: Option<String>,
+    /// Whether every bucket counts all observations up to its bound, defaults to true.
+    /// Buckets that only count their own range get accumulated
+    pub cumulative: Option<bool>,
+    /// Quantile by key, defaults to keys like `p50` or `p999`
+    pub quantiles: Option<BTreeMap<String, f64>>,
+    /// Key of the sum of observations, defaults to `sum`
+    pub sum: Option<String>,
+    /// Key of the number of observations, defaults to `count`
+    pub count: Option<String>
+}
+
+impl Distribution {
+    pub fn bucket_prefix(&self) -> &str {
+        self.bucket_prefix.as_deref().unwrap_or("le_")
+    }
+
+    pub fn is_cumulative(&self) -> bool {
+        self.cumulative.unwrap_or(true)
+    }
+
+    pub fn sum_key(&self) -> &str {
+        self.sum.as_deref().unwrap_or("sum")
+    }
+
+    pub fn count_key(&self) -> &str {
+        self.count.as_deref().unwrap_or("count")
+    }
+}
+
+/// Conversion of a payload that is an array of records, such as NDJSON.
+/// Every record becomes one sample, all selectors are evaluated against the record
+#[derive(Deserialize, Clone, Debug)]
+pub struct Records {
+    /// Selector of the metric name, defaults to `.name`
+    pub name: Option<String>,
+    /// Selector of the sample value, defaults to `.value`
+    pub value: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(rename = "type")]
+    pub metric_type: Option<MetricType>,
+    pub help: Option<String>,
+    /// Replaces the value mappings of the module for the records
+    pub value_mappings: Option<ValueMappings>,
+    pub value_parser: Option<ValueParser>
+}
+
+/// Maps strings, such as `green` or `DEGRADED`, to sample values.
+/// `values` are matched ignoring case, then `patterns` in order.
+/// Strings matching neither get the `fallback` value, if any
+#[derive(Deserialize, Clone, Debug)]
+#[serde(from = "ConfiguredValueMappings")]
+pub struct ValueMappings {
+    /// Keyed by the lowercased string
+    pub values: Option<HashMap<String, f64>>,
+    pub patterns: Option<Vec<ValuePattern>>,
+    pub fallback: Option<f64>,
+    /// Configured keys that only differ in case, rejected by `Module::validate`
+    ambiguous_keys: Option<(String, String)>
+}
+
+#[derive(Deserialize)]
+struct ConfiguredValueMappings {
+    values: Option<BTreeMap<String, f64>>,
+    patterns: Option<Vec<ValuePattern>>,
+    fallback: Option<f64>
+}
+
+impl From<ConfiguredValueMappings> for ValueMappings {
+    fn from(configured: ConfiguredValueMappings) -> Self {
+        let mut ambiguous_keys = None;
+        let values = configured.values.map(|values| {
+            let mut lowercased: HashMap<String, (String, f64)> = HashMap::new();
+            for (key, value) in values {
+                if let Some((other_key, _)) = lowercased.insert(key.to_lowercase(), (key.to_string(), value)) {
+                    ambiguous_keys.get_or_insert((other_key, key));
+                }
+            }
+            lowercased.into_iter().map(|(key, (_, value))| (key, value)).collect()
+        });
+
+        Self {
+            values,
+            patterns: configured.patterns,
+            fallback: configured.fallback,
+            ambiguous_keys
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ValuePattern {
+    #[serde(deserialize_with = "deserialize_required_regex")]
+    pub regex: Regex,
+    pub value: f64
+}
+
+impl ValueMappings {
+    /// The fallback is left out, strings known to the exporter come before it
+    pub fn map(&self, value: &str) -> Option<f64> {
+        let mapped_value = self.values.as_ref()
+            .and_then(|values| values.get(&value.to_lowercase()))
+            .copied();
+
+        mapped_value.or_else(|| self.patterns.iter()
+            .flatten()
+            .find(|pattern| pattern.regex.is_match(value))
+            .map(|pattern| pattern.value))
+    }
+}
+
+/// States of an enum-like field, reported as one 0/1 series per state
+#[derive(Deserialize, Clone, Debug)]
+pub struct StateSet {
+    pub states: Vec<String>,
+    /// State reported for values not listed in `states`
+    pub other: Option<String>,
+    /// Values not listed in `states` become a state of their own
+    #[serde(default)]
+    pub discover: bool,
+    /// States discovered so far by metric name, they keep being reported as 0.
+    /// Shared by clones of the module and dropped on reload
+    #[serde(skip)]
+    discovered: Arc<Mutex<HashMap<String, Vec<String>>>>
+}
+
+impl StateSet {
+    /// One `(state, value)` pair per state of `metric_name`, matched ignoring case.
+    /// Missing fields report 0 for every state
+    pub fn samples(&self, metric_name: &str, field_value: Option<&str>) -> Vec<(String, f64)> {
+        let is_current = |state: &str| field_value.is_some_and(|value| value.to_lowercase() == state.to_lowercase());
+        let mut samples = self.states.iter()
+            .map(|state| (state.to_string(), is_current(state) as i64 as f64))
+            .collect::<Vec<_>>();
+
+        let unknown_value = field_value.filter(|_| samples.iter().all(|(_, value)| *value == 0.0));
+        let mut is_other = unknown_value.is_some();
+        if self.discover {
+            let mut discovered = self.discovered.lock().unwrap();
+            let states = discovered.entry(metric_name.to_string()).or_default();
+            if let Some(value) = unknown_value {
+                if !states.iter().any(|state| is_current(state)) && states.len() < MAX_DISCOVERED_STATES {
+                    states.push(value.to_string());
+                }
+            }
+            for state in states.iter() {
+                is_other &= !is_current(state);
+                samples.push((state.to_string(), is_current(state) as i64 as f64));
+            }
+        }
+        if let Some(other) = &self.other {
+            samples.push((other.to_string(), is_other as i64 as f64));
+        }
+        samples
+    }
+}
+
+/// How a scalar sibling of `gauge_field` gets reported
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldMode {
+    /// Label of every metric of the object
+    Label,
+    /// Label of the `<object>_info` metric
+    Info,
+    /// Metric of its own, named `<object>_<field>`
+    Metric
+}
+
+/// Recursive conversion of every numeric and boolean leaf
+#[derive(Deserialize, Clone, Debug)]
+pub struct Flatten {
+    /// Joins the key path into a metric name, defaults to `_`
+    pub separator: Option<String>,
+    pub max_depth: Option<usize>,
+    /// Children of these keys become labels, e.g. `backend: backend_name`
+    pub label_keys: Option<HashMap<String, String>>,
+    /// Keys matching this pattern become labels named after their parent key
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub id_pattern: Option<Regex>
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    deserialize_required_regex(deserializer).map(Some)
+}
+
+fn deserialize_required_regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// Overrides the `# HELP` and `# TYPE` lines of a metric family
+#[derive(Deserialize, Clone, Debug)]
+pub struct MetricMetadata {
+    pub help: Option<String>,
+    #[serde(rename = "type")]
+    pub metric_type: Option<MetricType>
+}
+
+/// Secret value given inline or read from a file or environment variable.
+/// Files and variables are read on every fetch, so rotated secrets get picked up
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Secret {
+    Inline(String),
+    File { file: String },
+    Env { env: String }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BasicAuth {
+    pub username: Secret,
+    pub password: Option<Secret>
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TlsVersion {
+    #[serde(rename = "TLS12")]
+    Tls12,
+    #[serde(rename = "TLS13")]
+    Tls13
+}
+
+/// TLS options of the upstream connection. Without a `ca_file`, the system roots are trusted
+#[derive(Deserialize, Clone, Debug)]
+pub struct TlsConfig {
+    pub ca_file: Option<String>,
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+    /// Name the server certificate gets verified against instead of the URL host
+    pub server_name: Option<String>,
+    pub min_version: Option<TlsVersion>,
+    #[serde(default)]
+    pub insecure_skip_verify: bool
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Get,
+    Post,
+    Put,
+    Patch
+}
+
+/// Body sent with the upstream request, either `content` or `file` is required.
+/// With `template` enabled, `{{ env.NAME }}` and `{{ target }}` placeholders get replaced
+#[derive(Deserialize, Clone, Debug)]
+pub struct RequestBody {
+    pub content: Option<String>,
+    pub file: Option<String>,
+    /// Defaults to `application/json`
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub template: bool
+}
+
+/// Options of the HTTP client fetching the JSON document
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpClientConfig {
+    pub method: Option<HttpMethod>,
+    pub body: Option<RequestBody>,
+    pub query_params: Option<BTreeMap<String, Secret>>,
+    pub headers: Option<BTreeMap<String, Secret>>,
+    pub basic_auth: Option<BasicAuth>,
+    pub bearer_token: Option<Secret>,
+    pub tls_config: Option<TlsConfig>
+}
+
+/// One conversion profile. A config file holds one or more of them
+#[derive(Deserialize, Clone, Debug)]
+pub struct Module {
+    pub entry_point: Option<String>,
+    /// Overrides the format detected from the `Content-Type` header or the file extension
+    pub format: Option<InputFormat>,
+    #[serde(default)]
     pub gauge_field: String,
     pub global_labels: Option<Vec<GlobalLabel>>,
     pub global_prefix: Option<String>,
     pub gauge_field_values: Option<Vec<String>>,
-    pub includes: Option<Vec<Include>>
+    /// Fields reported as state sets, by field name. Takes precedence over `gauge_field_values`
+    pub state_sets: Option<HashMap<String, StateSet>>,
+    /// Puts string fields into an `<object>_info` metric and reports numbers and booleans
+    /// as metrics of their own, instead of copying every field onto the metrics as a label
+    pub info_metrics: Option<bool>,
+    /// Overrides how single fields are reported, by field name
+    pub field_modes: Option<HashMap<String, FieldMode>>,
+    pub includes: Option<Vec<Include>>,
+    pub metrics: Option<Vec<MetricMapping>>,
+    pub records: Option<Records>,
+    /// Applies to every rule that has no value mappings of its own
+    pub value_mappings: Option<ValueMappings>,
+    /// Parsers of automatically converted fields, by field name
+    pub field_parsers: Option<HashMap<String, ValueParser>>,
+    pub auto_convert: Option<bool>,
+    pub flatten: Option<Flatten>,
+    pub metric_metadata: Option<HashMap<String, MetricMetadata>>,
+    #[serde(default)]
+    pub name_sanitization: NameSanitization,
+    pub http_client: Option<HttpClientConfig>,
+    /// Seconds a scrape may take, including the conversion.
+    /// Capped by the scrape timeout Prometheus sends
+    pub timeout: Option<f64>,
+    /// Fail the whole scrape when a single rule fails instead of skipping the rule
+    pub strict: Option<bool>,
+    /// Selectors of this module compiled so far, shared by its clones and dropped on reload
+    #[serde(skip)]
+    pub jq: Jq,
+    /// Built from `http_client` when the config gets loaded, every fetch of the module reuses it
+    #[serde(skip)]
+    pub client: reqwest::Client
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ConfigFile {
+    pub modules: HashMap<String, Module>
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ConfigError {
     IOError(std::io::Error),
-    YamlError(serde_yaml::Error)
+    YamlError(serde_yaml::Error),
+    ModuleError(String),
+    ValidationError(String)
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::IOError(e) => write!(f, "{}", e),
+            ConfigError::YamlError(e) => write!(f, "{}", e),
+            ConfigError::ModuleError(e) => write!(f, "{}", e),
+            ConfigError::ValidationError(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 impl ConfigFile {
+    /// Files without a `modules:` section are loaded as a single module named `default`
     pub fn from_str(yml_str: &str) -> Result<ConfigFile, ConfigError> {
-        let config :ConfigFile = serde_yaml::from_str(&yml_str)
-            .or_else(|err| Err(ConfigError::YamlError(err)))?;
+        let yaml: serde_yaml::Value = serde_yaml::from_str(yml_str)
+            .map_err(ConfigError::YamlError)?;
+
+        let mut config: ConfigFile = if yaml.get("modules").is_some() {
+            serde_yaml::from_value(yaml).map_err(ConfigError::YamlError)?
+        } else {
+            let module: Module = serde_yaml::from_value(yaml).map_err(ConfigError::YamlError)?;
+            let mut modules = HashMap::new();
+            modules.insert(DEFAULT_MODULE.to_string(), module);
+            ConfigFile { modules }
+        };
+
+        if config.modules.is_empty() {
+            return Err(ConfigError::ModuleError("Config file does not define any modules".to_string()))
+        }
+
+        for (name, module) in config.modules.iter_mut() {
+            module.validate()
+                .map_err(|err| ConfigError::ValidationError(format!("Module {}: {}", name, err)))?;
+            module.client = http_client::build_client(module.http_client.as_ref())
+                .map_err(|err| ConfigError::ValidationError(format!("Module {}: {}", name, err)))?;
+        }
 
         Ok(config)
     }
 
-    pub fn from_file(path: &str) -> Result<ConfigFile, ConfigError> {
-        let contents = fs::read_to_string(path)
-                        .or_else(|err| Err(ConfigError::IOError(err)))?;
-        ConfigFile::from_str(&contents)
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.modules.get(name)
     }
+}
 
-    pub fn validate_config_file(path: &str) -> Result<(), ConfigError> {
-        if let Err(err) = ConfigFile::from_file(path) {
-            Err(err)
-        }
-        else {
-            Ok(())
-        }
+impl Module {
+    /// Parses a single module config, panics on invalid YAML
+    #[cfg(test)]
+    pub fn from_yaml_for_test(yaml_str: &str) -> Module {
+        ConfigFile::from_str(yaml_str).unwrap().modules.remove(DEFAULT_MODULE).unwrap()
     }
 
     pub fn has_gauge_values(&self) -> bool {
         self.gauge_field_values.is_some()
     }
-}
\ No newline at end of file
+
+    /// Whether the JSON payload gets walked and converted automatically,
+    /// in addition to the explicit `metrics` mappings
+    pub fn auto_convert(&self) -> bool {
+        self.auto_convert.unwrap_or(true)
+    }
+
+    /// Value mappings of a rule, falling back to those of the module
+    pub fn value_mappings<'a>(&'a self, rule_mappings: &'a Option<ValueMappings>) -> Option<&'a ValueMappings> {
+        rule_mappings.as_ref().or(self.value_mappings.as_ref())
+    }
+
+    pub fn state_set(&self, field_name: &str) -> Option<&StateSet> {
+        self.state_sets.as_ref()?.get(field_name)
+    }
+
+    pub fn field_mode(&self, field_name: &str, field_value: &Value) -> FieldMode {
+        match self.field_modes.as_ref().and_then(|field_modes| field_modes.get(field_name)) {
+            Some(field_mode) => *field_mode,
+            None if !self.info_metrics.unwrap_or(false) => FieldMode::Label,
+            None if field_value.is_string() => FieldMode::Info,
+            None => FieldMode::Metric
+        }
+    }
+
+    pub fn field_parser(&self, field_name: &str) -> Option<ValueParser> {
+        self.field_parsers.as_ref()?.get(field_name).copied()
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict.unwrap_or(false)
+    }
+
+    pub fn metric_metadata(&self, metric_name: &str) -> Option<&MetricMetadata> {
+        self.metric_metadata.as_ref()?.get(metric_name)
+    }
+
+    /// Rejects configured label names Prometheus reserves for internal use
+    /// and configurations relying on `gauge_field` without defining it
+    fn validate(&self) -> Result<(), String> {
+        if self.gauge_field.is_empty() && (self.includes.is_some() || self.has_gauge_values()) {
+            return Err("gauge_field is required when includes or gauge_field_values are configured".to_string())
+        }
+
+        if let Some(include) = self.includes.iter().flatten().find(|include| include.label_value.is_none() && include.label_selector.is_none()) {
+            return Err(format!("Include {} requires either label_selector or label_value", include.name))
+        }
+
+        if let Some(include) = self.includes.iter().flatten().find(|include| include.label_value == Some(IncludeLabelValue::Key) && include.iterate != Some(true)) {
+            return Err(format!("Include {} requires iterate: true for label_value key", include.name))
+        }
+
+        if self.http_client.as_ref().is_some_and(|client| client.basic_auth.is_some() && client.bearer_token.is_some()) {
+            return Err("http_client accepts either basic_auth or bearer_token, not both".to_string())
+        }
+
+        let tls_config = self.http_client.as_ref().and_then(|client| client.tls_config.as_ref());
+        if tls_config.is_some_and(|tls| tls.insecure_skip_verify && (tls.ca_file.is_some() || tls.server_name.is_some())) {
+            return Err("tls_config insecure_skip_verify disables verification, ca_file and server_name would be ignored".to_string())
+        }
+
+        if self.timeout.is_some_and(|timeout| timeout <= 0.0 || !timeout.is_finite()) {
+            return Err("timeout must be a positive number of seconds".to_string())
+        }
+
+        let mut value_mappings = vec!(&self.value_mappings);
+        value_mappings.extend(self.includes.iter().flatten().map(|include| &include.value_mappings));
+        value_mappings.extend(self.metrics.iter().flatten().map(|metric| &metric.value_mappings));
+        value_mappings.extend(self.records.iter().map(|records| &records.value_mappings));
+        if let Some((key, other_key)) = value_mappings.into_iter().flatten().find_map(|mappings| mappings.ambiguous_keys.as_ref()) {
+            return Err(format!("value_mappings keys {} and {} only differ in case", key, other_key))
+        }
+
+        let is_distribution = |metric_type: &Option<MetricType>| matches!(metric_type, Some(MetricType::Histogram) | Some(MetricType::Summary));
+        if self.records.as_ref().is_some_and(|records| is_distribution(&records.metric_type))
+            || self.metric_metadata.iter().flatten().any(|(_, metadata)| is_distribution(&metadata.metric_type)) {
+            return Err("Histograms and summaries can only be assembled by metrics entries".to_string())
+        }
+
+        if let Some(body) = self.http_client.as_ref().and_then(|client| client.body.as_ref()) {
+            if body.content.is_some() == body.file.is_some() {
+                return Err("http_client body requires either content or file".to_string())
+            }
+        }
+
+        let mut label_names = vec!(&self.gauge_field);
+        label_names.extend(self.global_labels.iter().flatten().map(|label| &label.name));
+        label_names.extend(self.includes.iter().flatten().map(|include| &include.label_name));
+        label_names.extend(self.flatten.iter().flat_map(|flatten| flatten.label_keys.iter().flatten().map(|(_, name)| name)));
+        label_names.extend(self.metrics.iter().flatten().flat_map(|metric| metric.labels.iter().flatten().map(|(name, _)| name)));
+        label_names.extend(self.records.iter().flat_map(|records| records.labels.iter().flatten().map(|(name, _)| name)));
+
+        match label_names.iter().find(|name| prom_name::is_reserved_label_name(name)) {
+            Some(name) => Err(format!("Label name {} is reserved, names starting with __ are not allowed", name)),
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::{ConfigError, ConfigFile, FieldMode, HttpMethod, IncludeLabelValue, Module, Secret, StateSet, DEFAULT_MODULE, MAX_DISCOVERED_STATES};
+    use crate::input_format::InputFormat;
+    use crate::prom_metric::MetricType;
+    use crate::prom_name::NameSanitization;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn single_module_file_loads_as_default_module() {
+        let yaml_str = r#"
+gauge_field: status
+global_prefix: single
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        assert_eq!(config.modules.len(), 1);
+        let module = config.module(DEFAULT_MODULE).unwrap();
+        assert_eq!(module.gauge_field, "status");
+        assert_eq!(module.global_prefix.as_deref(), Some("single"));
+        assert!(module.entry_point.is_none());
+    }
+
+    #[test]
+    fn modules_file_loads_every_module() {
+        let yaml_str = r#"
+modules:
+  router:
+    entry_point: .components
+    gauge_field: status
+    gauge_field_values:
+      - warning
+      - ok
+  queue:
+    gauge_field: state
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        assert_eq!(config.modules.len(), 2);
+        assert!(config.module(DEFAULT_MODULE).is_none());
+
+        let router = config.module("router").unwrap();
+        assert_eq!(router.entry_point.as_deref(), Some(".components"));
+        assert!(router.has_gauge_values());
+
+        let queue = config.module("queue").unwrap();
+        assert_eq!(queue.gauge_field, "state");
+        assert!(queue.entry_point.is_none());
+    }
+
+    #[test]
+    fn metric_metadata_is_looked_up_by_metric_name() {
+        let yaml_str = r#"
+gauge_field: status
+metric_metadata:
+  network_status:
+    type: counter
+    help: Network status
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        let module = config.module(DEFAULT_MODULE).unwrap();
+        let metadata = module.metric_metadata("network_status").unwrap();
+        assert_eq!(metadata.metric_type, Some(MetricType::Counter));
+        assert_eq!(metadata.help.as_deref(), Some("Network status"));
+        assert!(module.metric_metadata("router_status").is_none());
+    }
+
+    #[test]
+    fn reserved_label_names_return_error() {
+        let yaml_str = r#"
+gauge_field: status
+global_labels:
+  - name: __environment
+    selector: .environment
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn name_sanitization_defaults_to_replace() {
+        let config = ConfigFile::from_str("gauge_field: status").unwrap();
+        assert_eq!(config.module(DEFAULT_MODULE).unwrap().name_sanitization, NameSanitization::Replace);
+
+        let config = ConfigFile::from_str("gauge_field: status\nname_sanitization: strip").unwrap();
+        assert_eq!(config.module(DEFAULT_MODULE).unwrap().name_sanitization, NameSanitization::Strip);
+    }
+
+    #[test]
+    fn modules_file_without_modules_returns_error() {
+        assert_matches!(ConfigFile::from_str("modules: {}"), Err(ConfigError::ModuleError(_)));
+    }
+
+    #[test]
+    fn invalid_module_returns_error() {
+        let yaml_str = r#"
+modules:
+  router:
+    global_prefix: [not, a, string]
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::YamlError(_)));
+    }
+
+    #[test]
+    fn includes_without_gauge_field_return_error() {
+        let yaml_str = r#"
+includes:
+    - name: router_backend_status
+      label_name: backend
+      label_selector: .router.backend
+      selector:
+        - ".router.backend.back1"
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn value_mappings_differing_in_case_return_error() {
+        let yaml_str = r#"
+metrics:
+    - name: router_up
+      value: .router.state
+      value_mappings:
+        values:
+          Up: 1
+          UP: 0
+"#;
+        match ConfigFile::from_str(yaml_str) {
+            Err(ConfigError::ValidationError(err)) => assert!(err.ends_with("value_mappings keys UP and Up only differ in case"), "{}", err),
+            other => panic!("Expected a validation error, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn value_mappings_match_keys_ignoring_case() {
+        let config = ConfigFile::from_str("value_mappings:\n  values:\n    Degraded: 0.5\n").unwrap();
+        let mappings = config.module(DEFAULT_MODULE).unwrap().value_mappings.as_ref().unwrap();
+
+        assert_eq!(mappings.map("DEGRADED"), Some(0.5));
+        assert_eq!(mappings.map("degraded"), Some(0.5));
+    }
+
+    #[test]
+    fn include_without_label_source_returns_error() {
+        let yaml_str = r#"
+gauge_field: status
+includes:
+    - name: router_backend_status
+      label_name: backend
+      selector: ".router.backend[]"
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn include_labelled_by_key_without_iterate_returns_error() {
+        let yaml_str = r#"
+gauge_field: status
+includes:
+    - name: router_backend_status
+      label_name: backend
+      selector: ".router.backend[]"
+      label_value: key
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(message)) if message.contains("iterate"));
+    }
+
+    #[test]
+    fn include_label_value_variants() {
+        let yaml_str = r#"
+gauge_field: status
+includes:
+    - name: by_key
+      label_name: backend
+      selector: ".router.backend"
+      label_value: key
+      iterate: true
+    - name: by_index
+      label_name: index
+      selector: ".items[]"
+      label_value: index
+    - name: by_selector
+      label_name: item
+      selector:
+        - ".items[]"
+      label_value: .name
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        let includes = config.module(DEFAULT_MODULE).unwrap().includes.as_ref().unwrap();
+        assert_eq!(includes[0].selector, vec![".router.backend"]);
+        assert_eq!(includes[0].label_value, Some(IncludeLabelValue::Key));
+        assert_eq!(includes[0].iterate, Some(true));
+        assert_eq!(includes[1].label_value, Some(IncludeLabelValue::Index));
+        assert_eq!(includes[2].label_value, Some(IncludeLabelValue::Selector(".name".to_string())));
+    }
+
+    #[test]
+    fn flatten_with_invalid_id_pattern_returns_error() {
+        let yaml_str = r#"
+flatten:
+  id_pattern: "[0-9"
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::YamlError(_)));
+    }
+
+    #[test]
+    fn metric_mappings_without_gauge_field() {
+        let yaml_str = r#"
+auto_convert: false
+metrics:
+  - name: router_uplinks
+    value: .components.router.num_uplinks
+    type: counter
+    help: Number of router uplinks
+    labels:
+      router: .components.router.name
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        let module = config.module(DEFAULT_MODULE).unwrap();
+        assert!(!module.auto_convert());
+
+        let mapping = &module.metrics.as_ref().unwrap()[0];
+        assert_eq!(mapping.name, "router_uplinks");
+        assert_eq!(mapping.metric_type, Some(MetricType::Counter));
+        assert_eq!(mapping.labels.as_ref().unwrap()["router"], ".components.router.name");
+    }
+
+    #[test]
+    fn http_client_secrets_from_inline_file_and_env() {
+        let yaml_str = r#"
+http_client:
+  headers:
+    Accept: application/json
+    X-Api-Key:
+      env: STATUS_API_KEY
+  basic_auth:
+    username: monitoring
+    password:
+      file: /etc/json_exporter/password
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        let http_client = config.module(DEFAULT_MODULE).unwrap().http_client.as_ref().unwrap();
+        let headers = http_client.headers.as_ref().unwrap();
+        assert_eq!(headers["Accept"], Secret::Inline("application/json".to_string()));
+        assert_eq!(headers["X-Api-Key"], Secret::Env { env: "STATUS_API_KEY".to_string() });
+
+        let basic_auth = http_client.basic_auth.as_ref().unwrap();
+        assert_eq!(basic_auth.username, Secret::Inline("monitoring".to_string()));
+        assert_eq!(basic_auth.password, Some(Secret::File { file: "/etc/json_exporter/password".to_string() }));
+    }
+
+    #[test]
+    fn http_client_with_basic_auth_and_bearer_token_returns_error() {
+        let yaml_str = r#"
+http_client:
+  basic_auth:
+    username: monitoring
+  bearer_token: token
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn tls_config_with_missing_ca_file_returns_error() {
+        let yaml_str = r#"
+http_client:
+  tls_config:
+    ca_file: /nonexistent/ca.pem
+    min_version: TLS13
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn tls_config_with_insecure_skip_verify_and_ca_file_returns_error() {
+        let yaml_str = r#"
+http_client:
+  tls_config:
+    ca_file: /etc/json_exporter/ca.pem
+    server_name: status.internal
+    insecure_skip_verify: true
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(message)) if message.contains("insecure_skip_verify"));
+    }
+
+    #[test]
+    fn tls_config_with_cert_file_but_no_key_file_returns_error() {
+        let yaml_str = r#"
+http_client:
+  tls_config:
+    cert_file: /nonexistent/client.pem
+    insecure_skip_verify: true
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn http_client_with_method_and_body() {
+        let yaml_str = r#"
+http_client:
+  method: POST
+  body:
+    file: /etc/json_exporter/query.graphql
+    template: true
+  query_params:
+    format: json
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        let http_client = config.module(DEFAULT_MODULE).unwrap().http_client.as_ref().unwrap();
+        assert_eq!(http_client.method, Some(HttpMethod::Post));
+        assert!(http_client.body.as_ref().unwrap().template);
+        assert_eq!(http_client.query_params.as_ref().unwrap()["format"], Secret::Inline("json".to_string()));
+    }
+
+    #[test]
+    fn http_client_body_with_content_and_file_returns_error() {
+        let yaml_str = r#"
+http_client:
+  method: POST
+  body:
+    content: "{}"
+    file: /etc/json_exporter/query.json
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn module_with_format_override() {
+        let yaml_str = r#"
+format: xml
+entry_point: .status
+"#;
+        let config = ConfigFile::from_str(yaml_str).unwrap();
+        assert_eq!(config.module(DEFAULT_MODULE).unwrap().format, Some(InputFormat::Xml));
+    }
+
+    #[test]
+    fn histogram_metadata_returns_error() {
+        let yaml_str = r#"
+metric_metadata:
+  request_duration_seconds:
+    type: histogram
+"#;
+        assert_matches!(ConfigFile::from_str(yaml_str), Err(ConfigError::ValidationError(_)));
+    }
+
+    #[test]
+    fn field_mode_defaults() {
+        let yaml_str = "gauge_field: status\nfield_modes:\n  region: metric\n";
+        let mut module = Module::from_yaml_for_test(yaml_str);
+
+        assert_eq!(module.field_mode("name", &json!("router")), FieldMode::Label);
+        assert_eq!(module.field_mode("region", &json!("2")), FieldMode::Metric);
+
+        module.info_metrics = Some(true);
+        assert_eq!(module.field_mode("name", &json!("router")), FieldMode::Info);
+        assert_eq!(module.field_mode("uplinks", &json!(2)), FieldMode::Metric);
+        assert_eq!(module.field_mode("enabled", &json!(true)), FieldMode::Metric);
+    }
+
+    #[test]
+    fn state_set_samples() {
+        let state_set: StateSet = serde_yaml::from_str("states: [ok, warning]\nother: unknown\n").unwrap();
+
+        assert_eq!(state_set.samples("router_status", Some("OK")), vec![("ok".to_string(), 1.0), ("warning".to_string(), 0.0), ("unknown".to_string(), 0.0)]);
+        assert_eq!(state_set.samples("router_status", Some("critical")), vec![("ok".to_string(), 0.0), ("warning".to_string(), 0.0), ("unknown".to_string(), 1.0)]);
+        assert_eq!(state_set.samples("router_status", None), vec![("ok".to_string(), 0.0), ("warning".to_string(), 0.0), ("unknown".to_string(), 0.0)]);
+    }
+
+    #[test]
+    fn state_set_discovers_unknown_states() {
+        let state_set: StateSet = serde_yaml::from_str("states: [ok, warning]\ndiscover: true\n").unwrap();
+
+        assert_eq!(state_set.samples("router_status", Some("critical")), vec![("ok".to_string(), 0.0), ("warning".to_string(), 0.0), ("critical".to_string(), 1.0)]);
+        assert_eq!(state_set.samples("router_status", Some("warning")), vec![("ok".to_string(), 0.0), ("warning".to_string(), 1.0), ("critical".to_string(), 0.0)]);
+        assert_eq!(state_set.samples("network_status", Some("ok")), vec![("ok".to_string(), 1.0), ("warning".to_string(), 0.0)]);
+        assert_eq!(state_set.clone().samples("router_status", None), vec![("ok".to_string(), 0.0), ("warning".to_string(), 0.0), ("critical".to_string(), 0.0)]);
+    }
+
+    #[test]
+    fn state_set_discovers_a_bounded_number_of_states() {
+        let state_set: StateSet = serde_yaml::from_str("states: [ok]
+other: unknown
+discover: true
+").unwrap();
+        for index in 0..MAX_DISCOVERED_STATES {
+            state_set.samples("router_status", Some(&format!("state_{}", index)));
+        }
+
+        let samples = state_set.samples("router_status", Some("critical"));
+        assert_eq!(samples.len(), MAX_DISCOVERED_STATES + 2);
+        assert!(samples.iter().all(|(state, _)| state != "critical"));
+        assert_eq!(samples.last(), Some(&("unknown".to_string(), 1.0)));
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config_file::{ConfigError, ConfigFile};
+use crate::exposition::MetricFamily;
+use crate::prom_label::PromLabel;
+use crate::prom_metric::MetricType;
+
+/// Holds the active config file and swaps it on reload.
+/// A config that fails to load is rejected and the last good one stays active.
+pub struct ConfigReloader {
+    path: String,
+    config: RwLock<Arc<ConfigFile>>,
+    status: Mutex<ReloadStatus>
+}
+
+struct ReloadStatus {
+    contents: String,
+    last_reload_successful: bool,
+    last_success_timestamp: f64,
+    successes: u64,
+    failures: u64
+}
+
+impl ConfigReloader {
+    /// Loads the initial config, fails if it is invalid
+    pub fn new(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::IOError)?;
+        let config = ConfigFile::from_str(&contents)?;
+
+        Ok(Self {
+            path: path.to_string(),
+            config: RwLock::new(Arc::new(config)),
+            status: Mutex::new(ReloadStatus {
+                contents,
+                last_reload_successful: true,
+                last_success_timestamp: unix_timestamp(),
+                successes: 0,
+                failures: 0
+            })
+        })
+    }
+
+    pub fn config(&self) -> Arc<ConfigFile> {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        let mut status = self.status.lock().unwrap();
+        let contents = fs::read_to_string(&self.path).map_err(ConfigError::IOError);
+        if let Ok(contents) = &contents {
+            status.contents = contents.to_string();
+        }
+
+        match contents.and_then(|contents| ConfigFile::from_str(&contents)) {
+            Ok(config) => {
+                *self.config.write().unwrap() = Arc::new(config);
+                status.last_reload_successful = true;
+                status.last_success_timestamp = unix_timestamp();
+                status.successes += 1;
+                Ok(())
+            },
+            Err(err) => {
+                status.last_reload_successful = false;
+                status.failures += 1;
+                Err(err)
+            }
+        }
+    }
+
+    /// Reloads when the file contents differ from the last attempted load.
+    /// Returns `None` if nothing changed.
+    pub fn reload_if_changed(&self) -> Option<Result<(), ConfigError>> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        if contents == self.status.lock().unwrap().contents {
+            return None
+        }
+        Some(self.reload())
+    }
+
+    /// Reload status, appended to `/metrics`
+    pub fn metrics(&self) -> Vec<MetricFamily> {
+        let status = self.status.lock().unwrap();
+        let result_label = |result: &str| vec![PromLabel::new("result".to_string(), result.to_string())];
+        vec![
+            MetricFamily::new("json_exporter_config_last_reload_successful", "Whether the last configuration reload attempt was successful", MetricType::Gauge)
+                .with_sample(vec!(), if status.last_reload_successful { 1.0 } else { 0.0 }),
+            MetricFamily::new("json_exporter_config_last_reload_success_timestamp_seconds", "Timestamp of the last successful configuration reload", MetricType::Gauge)
+                .with_sample(vec!(), status.last_success_timestamp),
+            MetricFamily::new("json_exporter_config_reloads_total", "Number of configuration reload attempts", MetricType::Counter)
+                .with_sample(result_label("success"), status.successes as f64)
+                .with_sample(result_label("failure"), status.failures as f64)
+        ]
+    }
+}
+
+fn unix_timestamp() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |duration| duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::config_file::DEFAULT_MODULE;
+    use crate::exposition::ExpositionFormat;
+    use super::ConfigReloader;
+
+    fn config_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("json_exporter_{}_{}.yml", name, std::process::id()))
+    }
+
+    fn valid_config(prefix: &str) -> String {
+        format!("global_prefix: {}\n", prefix)
+    }
+
+    fn invalid_config() -> String {
+        "gauge_field_values:\n  - ok\n".to_string()
+    }
+
+    fn global_prefix(reloader: &ConfigReloader) -> Option<String> {
+        reloader.config().module(DEFAULT_MODULE).unwrap().global_prefix.clone()
+    }
+
+    fn sample_lines(reloader: &ConfigReloader) -> Vec<String> {
+        ExpositionFormat::Prometheus.render(&reloader.metrics())
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.contains("timestamp"))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reload_swaps_valid_config() {
+        let path = config_path("reload_valid");
+        fs::write(&path, valid_config("first")).unwrap();
+        let reloader = ConfigReloader::new(path.to_str().unwrap()).unwrap();
+
+        fs::write(&path, valid_config("second")).unwrap();
+        assert!(reloader.reload().is_ok());
+        assert_eq!(global_prefix(&reloader), Some("second".to_string()));
+        assert_eq!(sample_lines(&reloader), vec![
+            "json_exporter_config_last_reload_successful 1",
+            "json_exporter_config_reloads_total{result=\"success\"} 1",
+            "json_exporter_config_reloads_total{result=\"failure\"} 0"
+        ]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_keeps_last_good_config() {
+        let path = config_path("reload_invalid");
+        fs::write(&path, valid_config("first")).unwrap();
+        let reloader = ConfigReloader::new(path.to_str().unwrap()).unwrap();
+
+        fs::write(&path, invalid_config()).unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(global_prefix(&reloader), Some("first".to_string()));
+        assert_eq!(sample_lines(&reloader), vec![
+            "json_exporter_config_last_reload_successful 0",
+            "json_exporter_config_reloads_total{result=\"success\"} 0",
+            "json_exporter_config_reloads_total{result=\"failure\"} 1"
+        ]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload_if_changed_only_reloads_changed_files() {
+        let path = config_path("reload_changed");
+        fs::write(&path, valid_config("first")).unwrap();
+        let reloader = ConfigReloader::new(path.to_str().unwrap()).unwrap();
+        assert!(reloader.reload_if_changed().is_none());
+
+        fs::write(&path, invalid_config()).unwrap();
+        assert!(reloader.reload_if_changed().unwrap().is_err());
+        assert!(reloader.reload_if_changed().is_none());
+
+        fs::write(&path, valid_config("second")).unwrap();
+        assert!(reloader.reload_if_changed().unwrap().is_ok());
+        assert_eq!(global_prefix(&reloader), Some("second".to_string()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn new_rejects_invalid_config() {
+        let path = config_path("new_invalid");
+        fs::write(&path, invalid_config()).unwrap();
+        assert!(ConfigReloader::new(path.to_str().unwrap()).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,18 +1,16 @@
+use crate::jq::JqError;
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum CustomIncludeError {
-    IOError(std::io::Error),
-    JsonError(serde_json::Error),
-    SelectorError(String)
-}
-
-impl From<std::io::Error> for CustomIncludeError {
-    fn from(err: std::io::Error) -> Self {
-        CustomIncludeError::IOError(err)
-    }
+    JqError(JqError),
+    SelectorError(String),
+    MissingFieldError(String),
+    NonScalarValueError(String)
 }
 
-impl From<serde_json::Error> for CustomIncludeError {
-    fn from(err: serde_json::Error) -> Self {
-        CustomIncludeError::JsonError(err)
+impl From<JqError> for CustomIncludeError {
+    fn from(err: JqError) -> Self {
+        CustomIncludeError::JqError(err)
     }
 }
\ No newline at end of file
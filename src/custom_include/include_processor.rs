@@ -1,128 +1,200 @@
 use serde_json::Value;
 
-use crate::{config_file::{ConfigFile, Include}, jq::Jq, prom_label::PromLabel, prom_metric::PromMetric, utils};
+use crate::{config_file::{Include, IncludeLabelValue, Module}, jq::{Jq, JqInput}, prom_label::PromLabel, prom_metric::{MetricType, PromMetric}, utils};
 
 use super::error::CustomIncludeError;
 
 pub struct IncludeProcessor<'a> {
-    config: &'a ConfigFile,
+    config: &'a Module,
     include: &'a Include,
     jq: &'a Jq,
-    json_document: &'a str,
+    json_document: &'a JqInput,
     global_labels: &'a Option<Vec<PromLabel>>,
 }
 
 impl<'a> IncludeProcessor<'a> {
     pub fn new(
-        config: &'a ConfigFile,
+        config: &'a Module,
         include: &'a Include,
         jq: &'a Jq,
-        json_document: &'a str,
+        json_document: &'a JqInput,
         global_labels: &'a Option<Vec<PromLabel>>,
     ) -> Self {
         Self {
-            config: config,
-            include: include,
-            jq: jq,
-            json_document: json_document,
-            global_labels: global_labels,
+            config,
+            include,
+            jq,
+            json_document,
+            global_labels,
         }
     }
 
     pub fn create_metrics(&self) -> Result<Vec<PromMetric>, CustomIncludeError> {
         let mut metrics = vec![];
         for include_selector in &self.include.selector {
-            let json_object = self.resolve_json(&include_selector)?;
-            metrics.append(&mut self.json_object_to_metric(include_selector, json_object)?);
+            if let Some(label_value) = &self.include.label_value {
+                for (element_label_value, json_object) in self.resolve_elements(include_selector, label_value)? {
+                    metrics.append(&mut self.json_object_to_metric(element_label_value, json_object)?);
+                }
+            } else {
+                let json_object = self.resolve_json(include_selector)?;
+                let label_value = self.selector_label_value(include_selector)?;
+                metrics.append(&mut self.json_object_to_metric(label_value, json_object)?);
+            }
         }
 
         Ok(metrics)
     }
 
-    fn json_object_to_metric(&self, include_selector: &str, json_object: Value) -> Result<Vec<PromMetric>, CustomIncludeError> {
+    fn json_object_to_metric(&self, label_value: String, json_object: Value) -> Result<Vec<PromMetric>, CustomIncludeError> {
         let mut metrics = vec![];
 
-        if self.config.has_gauge_values() {
+        if let Some(state_set) = self.config.state_set(&self.config.gauge_field) {
+            let field_value = json_object.get(&self.config.gauge_field).and_then(utils::json_value_to_str);
+            for (state, value) in state_set.samples(&self.include.name, field_value.as_deref()) {
+                let mut labels = self.labels(&label_value);
+                labels.push(PromLabel::new(self.config.gauge_field.to_string(), state));
+                metrics.push(PromMetric::new(self.include.name.to_string(), Some(value), Some(labels))
+                    .with_type(MetricType::StateSet));
+            }
+        } else if self.config.has_gauge_values() {
             for gauge_field_value in self.config.gauge_field_values.as_ref().unwrap() {
-                let mut labels = self.labels(include_selector)?;
+                let mut labels = self.labels(&label_value);
                 labels.push(
                     PromLabel::new(self.config.gauge_field.to_string(), gauge_field_value.to_string())
                 );
-                let metric_value = self.metric_value(gauge_field_value, &json_object);
+                let metric_value = self.metric_value(gauge_field_value, &json_object)?;
                 metrics.push(PromMetric::new(
                     self.include.name.to_string(),
                     metric_value,
                     Some(labels.clone())
                 ));
             }
+        } else if let Some(json_value) = json_object.get(&self.config.gauge_field) {
+            let labels = self.labels(&label_value);
+
+            metrics.push(PromMetric::new(
+                self.include.name.to_string(),
+                utils::parse_json_value(json_value, self.include.value_parser, self.config.value_mappings(&self.include.value_mappings)),
+                Some(labels)
+            ));
         } else {
-            if let Some(json_value) = json_object.get(self.config.gauge_field.to_string()) {
-                let labels = self.labels(include_selector)?;
-
-                metrics.push(PromMetric::new(
-                    self.include.name.to_string(),
-                    utils::json_value_to_i64(json_value),
-                    Some(labels.clone())
-                ));
-            }else {
-                return Err(CustomIncludeError::SelectorError(format!("Key {} is not present in JSON object", self.config.gauge_field)))
-            }
+            return Err(CustomIncludeError::MissingFieldError(format!("Key {} is not present in JSON object", self.config.gauge_field)))
         }
 
         Ok(metrics)
     }
 
-    fn labels(&self, include_selector: &str) -> Result<Vec<PromLabel>, CustomIncludeError> {
-        let label_values = self.fetch_label_values()?;
-        let label_value = label_values
-            .iter()
-            .find(|label_value| include_selector.ends_with(label_value.as_str()))
-            .unwrap();
-
+    fn labels(&self, label_value: &str) -> Vec<PromLabel> {
         let label = PromLabel::new(self.include.label_name.to_string(), label_value.to_string());
-        let all_labels = if self.global_labels.is_some() {
+        if self.global_labels.is_some() {
             let mut l = vec![label];
             l.append(&mut self.global_labels.clone().unwrap());
             l
         } else {
             vec![label]
+        }
+    }
+
+    /// Without `label_value`, the label value is the key below `label_selector`
+    /// the include selector ends with
+    fn selector_label_value(&self, include_selector: &str) -> Result<String, CustomIncludeError> {
+        self.fetch_label_values()?
+            .into_iter()
+            .find(|label_value| include_selector.ends_with(label_value.as_str()))
+            .ok_or_else(|| CustomIncludeError::SelectorError(format!(
+                "Selector {} does not match any key of {}",
+                include_selector,
+                self.label_selector()
+            )))
+    }
+
+    /// Resolves every element `include_selector` yields, together with its label value.
+    /// With `iterate`, the objects and arrays the selector yields get iterated here,
+    /// their entries are the elements so object keys are known.
+    fn resolve_elements(&self, include_selector: &str, label_value: &IncludeLabelValue) -> Result<Vec<(String, Value)>, CustomIncludeError> {
+        let values = self.jq.resolve_input(self.json_document, include_selector)?;
+        let elements = if self.include.iterate.unwrap_or(false) {
+            let mut elements = vec![];
+            for container in values {
+                match container {
+                    Value::Object(object) => elements.extend(object.into_iter().map(|(key, value)| (Some(key), value))),
+                    Value::Array(array) => elements.extend(array.into_iter().map(|value| (None, value))),
+                    _ => return Err(CustomIncludeError::SelectorError(format!(
+                        "Selector {} does not point to an object or array",
+                        include_selector
+                    )))
+                }
+            }
+            elements
+        } else {
+            values.into_iter().map(|value| (None, value)).collect()
         };
 
-        Ok(all_labels)
+        elements.into_iter()
+            .enumerate()
+            .map(|(index, (key, element))| {
+                let element_label_value = match label_value {
+                    IncludeLabelValue::Key => key.ok_or_else(|| CustomIncludeError::SelectorError(format!(
+                        "Elements of {} have no object key",
+                        include_selector
+                    )))?,
+                    IncludeLabelValue::Index => index.to_string(),
+                    IncludeLabelValue::Selector(selector) => self.jq.resolve(&element, selector)?
+                        .first()
+                        .and_then(utils::json_value_to_str)
+                        .ok_or_else(|| CustomIncludeError::SelectorError(format!(
+                            "Selector {} does not yield a scalar value for elements of {}",
+                            selector,
+                            include_selector
+                        )))?
+                };
+                Ok((element_label_value, element))
+            })
+            .collect()
+    }
+
+    fn label_selector(&self) -> &str {
+        self.include.label_selector.as_deref().unwrap_or(".")
     }
 
     fn fetch_label_values(&self) -> Result<Vec<String>, CustomIncludeError> {
-        let raw_json = self
-            .jq
-            .resolve_raw(self.json_document, &self.include.label_selector)?;
-        let json_value: Value = serde_json::from_str(&raw_json)?;
-        if json_value.is_object() {
-            let object_keys = json_value.as_object().unwrap().keys();
-            Ok(object_keys.map(|k| k.to_string()).collect::<Vec<String>>())
-        } else {
-            return Err(CustomIncludeError::SelectorError(format!(
+        match self.jq.resolve_input(self.json_document, self.label_selector())?.as_slice() {
+            [Value::Object(object)] => Ok(object.keys().cloned().collect()),
+            _ => Err(CustomIncludeError::SelectorError(format!(
                 "Selector {} does not point to a valid object",
-                self.include.label_selector
-            )));
+                self.label_selector()
+            )))
         }
     }
 
-    fn metric_value(&self, gauge_field_value: &str, json_object: &Value) -> Option<i64> {
+    fn metric_value(&self, gauge_field_value: &str, json_object: &Value) -> Result<Option<f64>, CustomIncludeError> {
         if let Some(gauge_value) = json_object.get(&self.config.gauge_field) {
-            let value = utils::json_value_to_str(gauge_value).unwrap();
+            let value = utils::json_value_to_str(gauge_value).ok_or_else(|| CustomIncludeError::NonScalarValueError(format!(
+                "Key {} of include {} is not a scalar value",
+                self.config.gauge_field,
+                self.include.name
+            )))?;
             if value.to_lowercase() == gauge_field_value.to_lowercase() {
-                Some(1)
+                Ok(Some(1.0))
             } else {
-                Some(0)
+                Ok(Some(0.0))
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
     fn resolve_json(&self, selector: &str) -> Result<Value, CustomIncludeError> {
-        let json_str = self.jq.resolve_raw(self.json_document, selector)?;
-        let json_value = serde_json::from_str(&json_str)?;
-        Ok(json_value)
+        let mut values = self.jq.resolve_input(self.json_document, selector)?;
+        if values.len() == 1 {
+            Ok(values.remove(0))
+        } else {
+            Err(CustomIncludeError::SelectorError(format!(
+                "Selector {} yields {} values, expected 1",
+                selector,
+                values.len()
+            )))
+        }
     }
 }
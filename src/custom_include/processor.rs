@@ -1,35 +1,52 @@
-use crate::{jq::Jq, prom_label::PromLabel, prom_metric::PromMetric};
-use crate::config_file::{ConfigFile, Include};
+use std::time::Instant;
+use crate::{jq::{Jq, JqInput}, prom_label::PromLabel, prom_metric::PromMetric};
+use crate::config_file::{Include, Module};
+use crate::payload_error::PayloadError;
+use crate::rule_error::RuleErrors;
 use super::{error::CustomIncludeError, include_processor::IncludeProcessor};
 
-pub struct CustomIncludeProcessor {
-    config: ConfigFile,
-    global_labels: Option<Vec<PromLabel>>,
-    json_document: String,
-    jq_instance: Jq
+pub struct CustomIncludeProcessor<'a> {
+    config: &'a Module,
+    global_labels: &'a Option<Vec<PromLabel>>,
+    json_document: &'a JqInput,
+    jq_instance: &'a Jq,
+    deadline: Option<Instant>
 }
 
-impl CustomIncludeProcessor {
+impl<'a> CustomIncludeProcessor<'a> {
     pub fn new(
-                json_doc :String,
-                config: ConfigFile,
-                global_labels: Option<Vec<PromLabel>>,
-                jq: Jq
+                json_doc: &'a JqInput,
+                config: &'a Module,
+                global_labels: &'a Option<Vec<PromLabel>>,
+                jq: &'a Jq
         ) -> Self {
         Self {
             json_document: json_doc,
             config,
-            global_labels: global_labels,
-            jq_instance: jq
+            global_labels,
+            jq_instance: jq,
+            deadline: None
         }
     }
 
-    pub fn process(&self) -> Result<Vec<PromMetric>, CustomIncludeError> {
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Every include is a rule of its own, see `RuleErrors`
+    pub fn process(&self, rule_errors: &RuleErrors) -> Result<Vec<PromMetric>, PayloadError> {
         let mut metrics = vec!();
 
         if let Some(custom_includes) = &self.config.includes {
             for include in custom_includes {
-                metrics.append(&mut self.process_include(include)?);
+                if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Err(PayloadError::TimeoutError)
+                }
+                let rule = format!("include:{}", include.name);
+                if let Some(mut include_metrics) = rule_errors.isolate(&rule, self.process_include(include))? {
+                    metrics.append(&mut include_metrics);
+                }
             }
         }
 
@@ -38,11 +55,11 @@ impl CustomIncludeProcessor {
 
     fn process_include(&self, include: &Include) -> Result<Vec<PromMetric>, CustomIncludeError> {
         let include_processor = IncludeProcessor::new(
-            &self.config,
+            self.config,
             include,
-            &self.jq_instance,
-            &self.json_document,
-            &self.global_labels
+            self.jq_instance,
+            self.json_document,
+            self.global_labels
         );
         let metrics = include_processor.create_metrics()?;
         Ok(metrics)
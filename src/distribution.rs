@@ -0,0 +1,241 @@
+use serde_json::{Map, Value};
+
+use crate::config_file::Distribution;
+use crate::prom_label::PromLabel;
+use crate::utils;
+
+/// One sample of a histogram or summary family
+#[derive(Debug)]
+pub struct DistributionSample {
+    pub suffix: &'static str,
+    /// `le` of buckets, `quantile` of quantiles
+    pub label: Option<PromLabel>,
+    pub value: f64
+}
+
+impl DistributionSample {
+    fn new(suffix: &'static str, label: Option<(&str, f64)>, value: f64) -> Self {
+        Self {
+            suffix,
+            label: label.map(|(name, bound)| PromLabel::new(name.to_string(), utils::format_metric_value(bound))),
+            value
+        }
+    }
+}
+
+/// Buckets sorted by their upper bound, ending with `+Inf`, followed by `_sum` and `_count`.
+/// A missing `+Inf` bucket holds the count, a missing count is taken from the `+Inf` bucket.
+/// Bucket counts that decrease with a higher bound are rejected
+pub fn histogram_samples(value: &Value, distribution: &Distribution) -> Result<Vec<DistributionSample>, String> {
+    let object = value.as_object().ok_or("histogram value is not an object")?;
+    let mut buckets = vec!();
+    for (key, bucket_value) in object {
+        if let Some(bound) = key.strip_prefix(distribution.bucket_prefix()) {
+            buckets.push((parse_bound(bound).ok_or(format!("invalid bucket bound {}", key))?, number(key, bucket_value)?));
+        }
+    }
+    if buckets.is_empty() {
+        return Err(format!("no bucket keys starting with {}", distribution.bucket_prefix()))
+    }
+
+    buckets.sort_by(|(bound, _), (other_bound, _)| bound.total_cmp(other_bound));
+    if !distribution.is_cumulative() {
+        let mut observations = 0.0;
+        for (_, bucket_count) in buckets.iter_mut() {
+            observations += *bucket_count;
+            *bucket_count = observations;
+        }
+    }
+
+    let count = match optional_number(object, distribution.count_key())? {
+        Some(count) => count,
+        None if buckets.last().is_some_and(|(bound, _)| *bound == f64::INFINITY) => buckets.last().unwrap().1,
+        None => return Err(format!("either a +Inf bucket or {} is required", distribution.count_key()))
+    };
+    if buckets.last().is_some_and(|(bound, _)| *bound != f64::INFINITY) {
+        buckets.push((f64::INFINITY, count));
+    }
+    if let Some(pair) = buckets.windows(2).find(|pair| pair[1].1 < pair[0].1) {
+        return Err(format!(
+            "bucket counts are not cumulative, le {} has {} observations but le {} only {}",
+            utils::format_metric_value(pair[0].0), pair[0].1, utils::format_metric_value(pair[1].0), pair[1].1
+        ))
+    }
+
+    let mut samples = buckets.into_iter()
+        .map(|(bound, bucket_count)| DistributionSample::new("_bucket", Some(("le", bound)), bucket_count))
+        .collect::<Vec<_>>();
+    samples.extend(sum_and_count(object, distribution, Some(count))?);
+    Ok(samples)
+}
+
+/// Quantiles sorted ascending, followed by `_sum` and `_count` if present
+pub fn summary_samples(value: &Value, distribution: &Distribution) -> Result<Vec<DistributionSample>, String> {
+    let object = value.as_object().ok_or("summary value is not an object")?;
+    let mut quantiles = vec!();
+    for (key, quantile_value) in object {
+        let quantile = match &distribution.quantiles {
+            Some(quantiles) => quantiles.get(key).copied(),
+            None => parse_percentile_key(key)
+        };
+        if let Some(quantile) = quantile {
+            quantiles.push((quantile, number(key, quantile_value)?));
+        }
+    }
+    if quantiles.is_empty() {
+        return Err("no quantile keys".to_string())
+    }
+
+    quantiles.sort_by(|(quantile, _), (other_quantile, _)| quantile.total_cmp(other_quantile));
+    let mut samples = quantiles.into_iter()
+        .map(|(quantile, quantile_value)| DistributionSample::new("", Some(("quantile", quantile)), quantile_value))
+        .collect::<Vec<_>>();
+    samples.extend(sum_and_count(object, distribution, None)?);
+    Ok(samples)
+}
+
+fn sum_and_count(object: &Map<String, Value>, distribution: &Distribution, count: Option<f64>) -> Result<Vec<DistributionSample>, String> {
+    let mut samples = vec!();
+    if let Some(sum) = optional_number(object, distribution.sum_key())? {
+        samples.push(DistributionSample::new("_sum", None, sum));
+    }
+    let count = match count {
+        Some(count) => Some(count),
+        None => optional_number(object, distribution.count_key())?
+    };
+    if let Some(count) = count {
+        samples.push(DistributionSample::new("_count", None, count));
+    }
+    Ok(samples)
+}
+
+/// `0.5`, `1`, `inf` or `+Inf`
+fn parse_bound(bound: &str) -> Option<f64> {
+    match bound.to_lowercase().as_str() {
+        "inf" | "+inf" => Some(f64::INFINITY),
+        bound => bound.parse::<f64>().ok().filter(|bound| bound.is_finite())
+    }
+}
+
+/// `p5` is the 0.05 quantile, `p50` the 0.5 quantile, `p999` the 0.999 quantile and `p100` the maximum
+fn parse_percentile_key(key: &str) -> Option<f64> {
+    let digits = key.strip_prefix('p').or_else(|| key.strip_prefix('P'))?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None
+    }
+    match digits {
+        "100" => Some(1.0),
+        digit if digit.len() == 1 => format!("0.0{}", digit).parse::<f64>().ok(),
+        digits => format!("0.{}", digits).parse::<f64>().ok()
+    }
+}
+
+fn number(key: &str, value: &Value) -> Result<f64, String> {
+    utils::json_value_to_f64(value, None).ok_or(format!("{} is not a number", key))
+}
+
+fn optional_number(object: &Map<String, Value>, key: &str) -> Result<Option<f64>, String> {
+    object.get(key).map(|value| number(key, value)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::config_file::Distribution;
+    use super::{histogram_samples, summary_samples, DistributionSample};
+
+    fn sample_lines(samples: Vec<DistributionSample>) -> Vec<String> {
+        samples.into_iter()
+            .map(|sample| match sample.label {
+                Some(label) => format!("{}{{{}}} {}", sample.suffix, label, sample.value),
+                None => format!("{} {}", sample.suffix, sample.value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn histogram_adds_inf_bucket() {
+        let histogram = json!({"le_0.5": 30, "le_0.1": 12, "count": 40, "sum": 9.3});
+        let samples = histogram_samples(&histogram, &Distribution::default()).unwrap();
+
+        assert_eq!(sample_lines(samples), vec![
+            "_bucket{le=\"0.1\"} 12",
+            "_bucket{le=\"0.5\"} 30",
+            "_bucket{le=\"+Inf\"} 40",
+            "_sum 9.3",
+            "_count 40"
+        ]);
+    }
+
+    #[test]
+    fn histogram_accumulates_non_cumulative_buckets() {
+        let histogram = json!({"bucket_1": 2, "bucket_5": 3, "bucket_inf": 1});
+        let distribution: Distribution = serde_yaml::from_str("bucket_prefix: bucket_\ncumulative: false\n").unwrap();
+        let samples = histogram_samples(&histogram, &distribution).unwrap();
+
+        assert_eq!(sample_lines(samples), vec![
+            "_bucket{le=\"1\"} 2",
+            "_bucket{le=\"5\"} 5",
+            "_bucket{le=\"+Inf\"} 6",
+            "_count 6"
+        ]);
+    }
+
+    #[test]
+    fn histogram_without_count_or_inf_bucket_returns_error() {
+        assert!(histogram_samples(&json!({"le_1": 2}), &Distribution::default()).is_err());
+        assert!(histogram_samples(&json!({"count": 2}), &Distribution::default()).is_err());
+        assert!(histogram_samples(&json!({"le_fast": 2, "count": 2}), &Distribution::default()).is_err());
+        assert!(histogram_samples(&json!(2), &Distribution::default()).is_err());
+    }
+
+    #[test]
+    fn histogram_with_decreasing_bucket_counts_returns_error() {
+        let err = histogram_samples(&json!({"le_0.1": 12, "le_0.5": 8, "count": 40}), &Distribution::default()).unwrap_err();
+        assert_eq!(err, "bucket counts are not cumulative, le 0.1 has 12 observations but le 0.5 only 8");
+        assert!(histogram_samples(&json!({"le_0.1": 12, "le_0.5": 30, "count": 20}), &Distribution::default()).is_err());
+        assert!(histogram_samples(&json!({"le_0.1": 12, "le_0.5": 12, "count": 12}), &Distribution::default()).is_ok());
+    }
+
+    #[test]
+    fn summary_from_percentile_keys() {
+        let summary = json!({"p99": 1.4, "p50": 0.2, "p999": 2.5, "count": 40, "max": 3.0});
+        let samples = summary_samples(&summary, &Distribution::default()).unwrap();
+
+        assert_eq!(sample_lines(samples), vec![
+            "{quantile=\"0.5\"} 0.2",
+            "{quantile=\"0.99\"} 1.4",
+            "{quantile=\"0.999\"} 2.5",
+            "_count 40"
+        ]);
+    }
+
+    #[test]
+    fn summary_from_single_digit_percentile_keys() {
+        let summary = json!({"p1": 0.01, "p5": 0.05, "p9": 0.09});
+        let samples = summary_samples(&summary, &Distribution::default()).unwrap();
+
+        assert_eq!(sample_lines(samples), vec![
+            "{quantile=\"0.01\"} 0.01",
+            "{quantile=\"0.05\"} 0.05",
+            "{quantile=\"0.09\"} 0.09"
+        ]);
+    }
+
+    #[test]
+    fn summary_keeps_p5_and_p50_apart() {
+        let summary = json!({"p50": 0.2, "p5": 0.1});
+        let samples = summary_samples(&summary, &Distribution::default()).unwrap();
+
+        assert_eq!(sample_lines(samples), vec!["{quantile=\"0.05\"} 0.1", "{quantile=\"0.5\"} 0.2"]);
+    }
+
+    #[test]
+    fn summary_with_configured_quantiles() {
+        let summary = json!({"median": 0.2, "p99": 1.4, "total_time": 8.0});
+        let distribution: Distribution = serde_yaml::from_str("quantiles:\n  median: 0.5\nsum: total_time\n").unwrap();
+        let samples = summary_samples(&summary, &distribution).unwrap();
+
+        assert_eq!(sample_lines(samples), vec!["{quantile=\"0.5\"} 0.2", "_sum 8"]);
+    }
+}
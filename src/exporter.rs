@@ -1,60 +1,128 @@
 use convert_case::{Case, Casing};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-use crate::{config_file::ConfigFile, prom_metric::PromMetric};
+use crate::{config_file::Module, exposition::{MetricFamily, Sample}, prom_label::{self, PromLabel}, prom_metric::{MetricType, PromMetric}, prom_name};
+use crate::rule_error::RuleError;
 
 pub struct Exporter<'a> {
-    config: &'a ConfigFile,
-    metrics: Vec<PromMetric>
+    config: &'a Module,
+    metrics: Vec<PromMetric>,
+    dropped_labels: RefCell<Vec<RuleError>>
 }
 
 impl<'a> Exporter<'a> {
-    pub(crate) fn new(config: &'a ConfigFile, metrics: Vec<PromMetric>) -> Self {
+    pub(crate) fn new(config: &'a Module, metrics: Vec<PromMetric>) -> Self {
         Self {
-            config: config,
-            metrics: metrics
+            config,
+            metrics,
+            dropped_labels: RefCell::new(vec!())
         }
     }
 
-    pub(crate) fn generate_metrics(&self) -> String {
-        let mut all_converted_metrics = vec!();
+    /// Labels `metric_families` dropped because their name is reserved, once per label name
+    pub(crate) fn rule_errors(&self) -> Vec<RuleError> {
+        self.dropped_labels.borrow().clone()
+    }
+
+    /// Groups metrics by their exported name, keeping the order in which families were discovered.
+    /// Prometheus rejects families mixing types, such families are left out
+    pub(crate) fn metric_families(&self) -> Vec<MetricFamily> {
+        let mut families: Vec<MetricFamily> = vec!();
+        let mut family_index: HashMap<String, usize> = HashMap::new();
+        let mut conflicting_types: HashMap<usize, MetricType> = HashMap::new();
 
         for metric in &self.metrics {
-            all_converted_metrics.push(self.metric_to_string(metric));
+            let family_name = self.metric_name(metric);
+            let metric_type = self.metric_type(metric);
+            let index = *family_index.entry(family_name.to_string()).or_insert_with(|| {
+                families.push(MetricFamily::new(&family_name, &self.metric_help(metric), metric_type));
+                families.len() - 1
+            });
+            if families[index].metric_type != metric_type {
+                conflicting_types.entry(index).or_insert(metric_type);
+            }
+            families[index].samples.push(Sample {
+                suffix: metric.suffix,
+                labels: self.metric_labels(&family_name, metric),
+                value: metric.value.unwrap_or(0.0)
+            });
         }
 
-        all_converted_metrics.join("\n").to_string()
+        families.into_iter()
+            .enumerate()
+            .filter(|(index, family)| match conflicting_types.get(index) {
+                Some(conflicting_type) => {
+                    eprintln!("ERR skipping metric family {}: samples are both {} and {}", family.name, family.metric_type, conflicting_type);
+                    false
+                },
+                None => true
+            })
+            .map(|(_, family)| family)
+            .collect()
+    }
+
+    fn metric_help(&self, metric: &PromMetric) -> String {
+        self.config.metric_metadata(&metric.name)
+            .and_then(|metadata| metadata.help.clone())
+            .or_else(|| metric.help.clone())
+            .unwrap_or_else(|| format!("Value of {} converted from JSON", metric.name))
     }
 
+    fn metric_type(&self, metric: &PromMetric) -> MetricType {
+        self.config.metric_metadata(&metric.name)
+            .and_then(|metadata| metadata.metric_type)
+            .unwrap_or(metric.metric_type)
+    }
+
+    /// Family name, counters end with `_total`
     fn metric_name(&self, metric: &PromMetric) -> String {
-        if let Some(metric_prefix) = &self.config.global_prefix {
-            format!("{}_{}", metric_prefix.to_case(Case::Snake), metric.name.to_string())
+        let mut metric_name = if let Some(metric_prefix) = &self.config.global_prefix {
+            format!("{}_{}", metric_prefix.to_case(Case::Snake), metric.name)
         }
         else {
             metric.name.to_string()
+        };
+        if self.metric_type(metric) == MetricType::Counter && !metric_name.ends_with("_total") {
+            metric_name.push_str("_total");
         }
+
+        prom_name::sanitize_metric_name(&metric_name, self.config.name_sanitization)
     }
 
-    fn metric_to_string(&self, metric: &PromMetric) -> std::string::String {
-        if metric.labels.is_none() {
-            format!("{} {}", self.metric_name(metric), metric.value.as_ref().unwrap_or(&0))
+    /// Sanitizes label names, names that end up the same get suffixed with `_2`, `_3`, ...
+    /// Labels using the reserved `__` prefix are dropped and reported as rule errors.
+    /// The state label of a state set is named after the metric family, as OpenMetrics requires
+    fn metric_labels(&self, family_name: &str, metric: &PromMetric) -> Vec<PromLabel> {
+        let labels = metric.labels.as_deref().unwrap_or_default();
+        let state_label_index = if metric.metric_type == MetricType::StateSet { labels.len().checked_sub(1) } else { None };
+        let mut metric_labels: Vec<PromLabel> = vec!();
+        for (index, label) in labels.iter().enumerate() {
+            let label_name = if Some(index) == state_label_index { family_name } else { &label.name };
+            let label_name = prom_name::sanitize_label_name(label_name, self.config.name_sanitization);
+            if prom_name::is_reserved_label_name(&label_name) {
+                self.drop_label(&label_name);
+                continue
+            }
+            metric_labels.push(PromLabel::new(prom_label::unique_label_name(&metric_labels, label_name), label.value.to_string()));
         }
-        else {
-            let labels = metric.labels
-                .as_ref()
-                .unwrap()
-                .iter()
-                .map(|label| label.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-
-            format!("{}{{{}}} {}", self.metric_name(metric), labels, metric.value.as_ref().unwrap_or(&0))
+        metric_labels
+    }
+
+    fn drop_label(&self, label_name: &str) {
+        let rule = format!("label:{}", label_name);
+        let mut dropped_labels = self.dropped_labels.borrow_mut();
+        if !dropped_labels.iter().any(|error| error.rule == rule) {
+            eprintln!("ERR dropping label {}: names starting with __ are reserved", label_name);
+            dropped_labels.push(RuleError { rule, reason: "reserved_label_name" });
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{config_file::{self, ConfigFile}, payload::Payload};
+    use crate::{config_file::Module, exposition::ExpositionFormat, payload::Payload, prom_label::PromLabel, prom_metric::{MetricType, PromMetric}};
+    use crate::rule_error::RuleError;
 
     use super::Exporter;
 
@@ -95,7 +163,7 @@ mod tests {
         .to_string()
     }
 
-    fn config_without_global_prefix() -> ConfigFile {
+    fn config_without_global_prefix() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -114,10 +182,10 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_global_prefix() -> ConfigFile {
+    fn config_with_global_prefix() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -137,10 +205,10 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_dashed_prefix() -> ConfigFile {
+    fn config_with_dashed_prefix() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -160,10 +228,10 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_camel_case_prefix() -> ConfigFile {
+    fn config_with_camel_case_prefix() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -183,20 +251,252 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_metric_metadata() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+metric_metadata:
+  router_backend_status:
+    type: untyped
+    help: "Status of a router backend\nas reported by the router"
+includes:
+    - name: router_backend_status
+      label_name: backend
+      label_selector: .router.backend
+      selector:
+        - ".router.backend.back1"
+        - ".router.backend.back2"
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_state_sets() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+state_sets:
+  status:
+    states: [ok, warning]
+    other: unknown
+  status_upstream:
+    states: [active, standby]
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_distributions() -> Module {
+        let yaml_str = r#"
+auto_convert: false
+metrics:
+  - name: request_duration_seconds
+    type: histogram
+    value: .latency
+  - name: rpc_duration_seconds
+    type: summary
+    value: .rpc
+  - name: requests
+    type: counter
+    value: .requests
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn sample_lines(metrics_payload: &str) -> Vec<&str> {
+        metrics_payload.lines().filter(|line| !line.starts_with('#')).collect()
     }
 
-    fn generate_metrics(config: &ConfigFile) -> String {
+    fn generate_metrics(config: &Module) -> String {
         let json_str = json_with_several_components();
         let payload = Payload::new(
             json_str,
             Some(".components".into()),
-            &config,
+            config,
         );
         let metrics = payload.json_to_metrics().unwrap();
+        let exporter = Exporter::new(config, metrics);
+        prometheus_text(&exporter)
+    }
+
+    fn prometheus_text(exporter: &Exporter) -> String {
+        ExpositionFormat::Prometheus.render(&exporter.metric_families())
+    }
+
+    #[test]
+    fn export_float_values() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("cpu_load".to_string(), Some(0.75), None),
+            PromMetric::new("upper_bound".to_string(), Some(f64::INFINITY), None),
+            PromMetric::new("ratio".to_string(), Some(f64::NAN), None),
+        ];
         let exporter = Exporter::new(&config, metrics);
-        let metrics_payload = exporter.generate_metrics();
-        metrics_payload
+
+        assert_eq!(sample_lines(&prometheus_text(&exporter)), vec![
+            "cpu_load 0.75",
+            "upper_bound +Inf",
+            "ratio NaN"
+        ]);
+    }
+
+    #[test]
+    fn export_groups_samples_by_exported_name() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("cpu.load".to_string(), Some(0.5), None),
+            PromMetric::new("cpu_load".to_string(), Some(0.75), None),
+            PromMetric::new("requests".to_string(), Some(3.0), None).with_type(MetricType::Counter),
+            PromMetric::new("requests_total".to_string(), Some(4.0), None).with_type(MetricType::Counter),
+        ];
+        let exporter = Exporter::new(&config, metrics);
+
+        assert_eq!(prometheus_text(&exporter).lines().collect::<Vec<_>>(), vec![
+            "# HELP cpu_load Value of cpu.load converted from JSON",
+            "# TYPE cpu_load gauge",
+            "cpu_load 0.5",
+            "cpu_load 0.75",
+            "# HELP requests_total Value of requests converted from JSON",
+            "# TYPE requests_total counter",
+            "requests_total 3",
+            "requests_total 4"
+        ]);
+    }
+
+    #[test]
+    fn export_skips_families_with_conflicting_types() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("requests".to_string(), Some(3.0), None).with_type(MetricType::Counter),
+            PromMetric::new("requests_total".to_string(), Some(4.0), None),
+            PromMetric::new("uptime".to_string(), Some(5.0), None),
+        ];
+        let exporter = Exporter::new(&config, metrics);
+
+        assert_eq!(sample_lines(&prometheus_text(&exporter)), vec!["uptime 5"]);
+    }
+
+    #[test]
+    fn export_groups_samples_by_family() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("network_status".to_string(), Some(1.0), Some(vec![PromLabel::new("status".to_string(), "ok".to_string())])),
+            PromMetric::new("router_status".to_string(), Some(0.0), None),
+            PromMetric::new("network_status".to_string(), Some(0.0), Some(vec![PromLabel::new("status".to_string(), "warning".to_string())])),
+        ];
+        let exporter = Exporter::new(&config, metrics);
+
+        assert_eq!(prometheus_text(&exporter).lines().collect::<Vec<_>>(), vec![
+            "# HELP network_status Value of network_status converted from JSON",
+            "# TYPE network_status gauge",
+            "network_status{status=\"ok\"} 1",
+            "network_status{status=\"warning\"} 0",
+            "# HELP router_status Value of router_status converted from JSON",
+            "# TYPE router_status gauge",
+            "router_status 0"
+        ]);
+    }
+
+    #[test]
+    fn export_state_sets_labels_states_by_family_name() {
+        let config = config_with_state_sets();
+        let metrics = Payload::new(json_with_several_components(), Some(".components".into()), &config).json_to_metrics().unwrap();
+        let families = Exporter::new(&config, metrics).metric_families();
+        let metrics_payload = ExpositionFormat::Prometheus.render(&families);
+
+        let state_sets = families.iter().filter(|family| family.metric_type == MetricType::StateSet).map(|family| family.name.as_str()).collect::<Vec<_>>();
+        assert!(state_sets.contains(&"network_status"));
+        assert!(state_sets.contains(&"network_status_upstream"));
+        assert_eq!(sample_lines(&metrics_payload).into_iter().filter(|line| line.starts_with("network_status{")).collect::<Vec<_>>(), vec![
+            "network_status{has_ip_addresses=\"true\",upstream_endpoints=\"54\",use_ip_v6=\"false\",network_status=\"ok\"} 1",
+            "network_status{has_ip_addresses=\"true\",upstream_endpoints=\"54\",use_ip_v6=\"false\",network_status=\"warning\"} 0",
+            "network_status{has_ip_addresses=\"true\",upstream_endpoints=\"54\",use_ip_v6=\"false\",network_status=\"unknown\"} 0",
+        ]);
+    }
+
+    #[test]
+    fn export_histograms_summaries_and_counters() {
+        let config = config_with_distributions();
+        let json_str = r#"{
+            "latency": {"le_0.1": 12, "le_0.5": 30, "count": 40, "sum": 9.3},
+            "rpc": {"p50": 0.2, "p99": 1.4},
+            "requests": 1024
+        }"#.to_string();
+        let metrics = Payload::new(json_str, None, &config).json_to_metrics().unwrap();
+        let metrics_payload = prometheus_text(&Exporter::new(&config, metrics));
+
+        assert!(metrics_payload.contains("# TYPE request_duration_seconds histogram\n"));
+        assert!(metrics_payload.contains("# TYPE rpc_duration_seconds summary\n"));
+        assert!(metrics_payload.lines().any(|line| line == "# TYPE requests_total counter"));
+        assert_eq!(sample_lines(&metrics_payload), vec![
+            "request_duration_seconds_bucket{le=\"0.1\"} 12",
+            "request_duration_seconds_bucket{le=\"0.5\"} 30",
+            "request_duration_seconds_bucket{le=\"+Inf\"} 40",
+            "request_duration_seconds_sum 9.3",
+            "request_duration_seconds_count 40",
+            "rpc_duration_seconds{quantile=\"0.5\"} 0.2",
+            "rpc_duration_seconds{quantile=\"0.99\"} 1.4",
+            "requests_total 1024"
+        ]);
+    }
+
+    #[test]
+    fn export_sanitizes_names_and_drops_reserved_labels() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("5xx.errors".to_string(), Some(3.0), Some(vec![
+                PromLabel::new("host name".to_string(), "web-1".to_string()),
+                PromLabel::new("__internal".to_string(), "x".to_string()),
+            ])),
+            PromMetric::new("4xx.errors".to_string(), Some(1.0), Some(vec![
+                PromLabel::new("__internal".to_string(), "y".to_string()),
+            ])),
+        ];
+        let exporter = Exporter::new(&config, metrics);
+
+        assert_eq!(sample_lines(&prometheus_text(&exporter)), vec![
+            "_5xx_errors{host_name=\"web-1\"} 3",
+            "_4xx_errors 1"
+        ]);
+        assert_eq!(exporter.rule_errors(), vec![RuleError {
+            rule: "label:__internal".to_string(),
+            reason: "reserved_label_name"
+        }]);
+    }
+
+    #[test]
+    fn export_suffixes_label_names_colliding_after_sanitizing() {
+        let config = config_without_global_prefix();
+        let metrics = vec![
+            PromMetric::new("requests".to_string(), Some(3.0), Some(vec![
+                PromLabel::new("host name".to_string(), "web-1".to_string()),
+                PromLabel::new("host_name".to_string(), "web-2".to_string()),
+                PromLabel::new("host-name".to_string(), "web-3".to_string()),
+            ])),
+        ];
+        let exporter = Exporter::new(&config, metrics);
+
+        assert_eq!(sample_lines(&prometheus_text(&exporter)), vec![
+            "requests{host_name=\"web-1\",host_name_2=\"web-2\",host_name_3=\"web-3\"} 3"
+        ]);
+    }
+
+    #[test]
+    fn export_uses_configured_metric_metadata() {
+        let config = config_with_metric_metadata();
+        let metrics_payload = generate_metrics(&config);
+        let lines = metrics_payload.lines().collect::<Vec<_>>();
+
+        assert!(lines.contains(&"# HELP router_backend_status Status of a router backend\\nas reported by the router"));
+        assert!(lines.contains(&"# TYPE router_backend_status untyped"));
+        assert_eq!(lines.iter().filter(|line| line.starts_with("# TYPE router_backend_status")).count(), 1);
+    }
+
+    #[test]
+    fn export_with_global_prefix_prefixes_metadata() {
+        let config = config_with_global_prefix();
+        let metrics_payload = generate_metrics(&config);
+
+        assert!(metrics_payload.lines().any(|line| line == "# TYPE prom_test_router_backend_status gauge"));
     }
 
     #[test]
@@ -211,8 +511,8 @@ includes:
         let metrics = payload.json_to_metrics().unwrap();
         let metric_name = metrics[0].name.to_string();
         let exporter = Exporter::new(&config, metrics);
-        let metrics_payload = exporter.generate_metrics();
-        let lines = metrics_payload.lines().collect::<Vec<_>>();
+        let metrics_payload = prometheus_text(&exporter);
+        let lines = sample_lines(&metrics_payload);
 
         assert!(lines[0].starts_with(&metric_name));
     }
@@ -222,7 +522,7 @@ includes:
         let config = config_with_global_prefix();
         let metrics_payload = generate_metrics(&config);
 
-        for metric in metrics_payload.lines() {
+        for metric in sample_lines(&metrics_payload) {
             assert!(
                 metric.starts_with("prom_test"),
                 "Expected metric name {} to start with prefix 'prom_test'",
@@ -243,9 +543,9 @@ includes:
         let metrics = payload.json_to_metrics().unwrap();
 
         let exporter = Exporter::new(&config, metrics);
-        let metrics_payload = exporter.generate_metrics();
+        let metrics_payload = prometheus_text(&exporter);
 
-        for metric in metrics_payload.lines() {
+        for metric in sample_lines(&metrics_payload) {
             assert!(
                 metric.starts_with("prom_test"),
                 "Expected metric name {} to start with prefix 'prom_test'",
@@ -266,9 +566,9 @@ includes:
         let metrics = payload.json_to_metrics().unwrap();
 
         let exporter = Exporter::new(&config, metrics);
-        let metrics_payload = exporter.generate_metrics();
+        let metrics_payload = prometheus_text(&exporter);
 
-        for metric in metrics_payload.lines() {
+        for metric in sample_lines(&metrics_payload) {
             assert!(
                 metric.starts_with("prom_test"),
                 "Expected metric name {} to start with prefix 'prom_test'",
@@ -0,0 +1,190 @@
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::prom_label::PromLabel;
+use crate::prom_metric::MetricType;
+use crate::utils;
+
+/// Exposition format of `/metrics` and `/probe`, negotiated through the `Accept` header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpositionFormat {
+    Prometheus,
+    OpenMetrics
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ExpositionFormat {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let accepts_openmetrics = request.headers()
+            .get("Accept")
+            .any(|accept| accept.contains("application/openmetrics-text"));
+        Outcome::Success(if accepts_openmetrics { ExpositionFormat::OpenMetrics } else { ExpositionFormat::Prometheus })
+    }
+}
+
+impl ExpositionFormat {
+    pub fn content_type(self) -> ContentType {
+        match self {
+            ExpositionFormat::Prometheus => ContentType::Plain,
+            ExpositionFormat::OpenMetrics => ContentType::with_params(
+                "application",
+                "openmetrics-text",
+                [("version", "1.0.0"), ("charset", "utf-8")]
+            )
+        }
+    }
+
+    /// Prometheus text ends after the last sample, OpenMetrics with `# EOF`
+    pub fn render(self, families: &[MetricFamily]) -> String {
+        let mut lines = vec!();
+        for family in families {
+            let (family_name, metric_type, help) = match self {
+                ExpositionFormat::Prometheus => (family.name.as_str(), family.prometheus_type(), escape_help(&family.help)),
+                ExpositionFormat::OpenMetrics => (family.openmetrics_name(), family.openmetrics_type(), escape_help(&family.help).replace('"', "\\\"")),
+            };
+            lines.push(format!("# HELP {} {}", family_name, help));
+            lines.push(format!("# TYPE {} {}", family_name, metric_type));
+            lines.extend(family.samples.iter().map(|sample| sample.render(&family.name)));
+        }
+        if self == ExpositionFormat::OpenMetrics {
+            lines.push("# EOF".to_string());
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Samples sharing one exported name, help text and type.
+/// `name` is the name the samples are exported under: counters end with `_total`, info metrics with `_info`
+#[derive(Clone, Debug)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    pub samples: Vec<Sample>
+}
+
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// Appended to the family name, such as `_bucket` or `_count`
+    pub suffix: &'static str,
+    pub labels: Vec<PromLabel>,
+    pub value: f64
+}
+
+impl MetricFamily {
+    pub fn new(name: &str, help: &str, metric_type: MetricType) -> Self {
+        Self {
+            name: name.to_string(),
+            help: help.to_string(),
+            metric_type,
+            samples: vec!()
+        }
+    }
+
+    pub fn with_sample(mut self, labels: Vec<PromLabel>, value: f64) -> Self {
+        self.samples.push(Sample { suffix: "", labels, value });
+        self
+    }
+
+    /// The Prometheus text format has no state sets and info metrics, they are reported as gauges
+    fn prometheus_type(&self) -> String {
+        match self.metric_type {
+            MetricType::StateSet | MetricType::Info => MetricType::Gauge.to_string(),
+            metric_type => metric_type.to_string()
+        }
+    }
+
+    fn openmetrics_type(&self) -> String {
+        match self.metric_type {
+            MetricType::Untyped => "unknown".to_string(),
+            metric_type => metric_type.to_string()
+        }
+    }
+
+    /// OpenMetrics names counter and info families without the suffix their samples carry
+    fn openmetrics_name(&self) -> &str {
+        let suffix = match self.metric_type {
+            MetricType::Counter => "_total",
+            MetricType::Info => "_info",
+            _ => ""
+        };
+        self.name.strip_suffix(suffix).unwrap_or(&self.name)
+    }
+}
+
+impl Sample {
+    fn render(&self, family_name: &str) -> String {
+        let value = utils::format_metric_value(self.value);
+        if self.labels.is_empty() {
+            format!("{}{} {}", family_name, self.suffix, value)
+        }
+        else {
+            let labels = self.labels.iter().map(|label| label.to_string()).collect::<Vec<_>>().join(",");
+            format!("{}{}{{{}}} {}", family_name, self.suffix, labels, value)
+        }
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prom_label::PromLabel;
+    use crate::prom_metric::MetricType;
+    use super::{ExpositionFormat, MetricFamily};
+
+    fn label(name: &str, value: &str) -> Vec<PromLabel> {
+        vec![PromLabel::new(name.to_string(), value.to_string())]
+    }
+
+    fn families() -> Vec<MetricFamily> {
+        vec![
+            MetricFamily::new("router_status", "Status of the \"router\"", MetricType::StateSet)
+                .with_sample(label("router_status", "ok"), 1.0)
+                .with_sample(label("router_status", "warning"), 0.0),
+            MetricFamily::new("requests_total", "Requests served", MetricType::Counter)
+                .with_sample(label("code", "200"), 42.0),
+            MetricFamily::new("queue_depth", "Depth of the queue", MetricType::Untyped)
+                .with_sample(vec!(), 3.0),
+            MetricFamily::new("router_info", "Information about router", MetricType::Info)
+                .with_sample(label("version", "1.2"), 1.0)
+        ]
+    }
+
+    #[test]
+    fn render_prometheus_reports_state_sets_as_gauges() {
+        let rendered = ExpositionFormat::Prometheus.render(&families());
+
+        assert!(rendered.contains("# TYPE router_status gauge\n"));
+        assert!(rendered.contains("# TYPE router_info gauge\n"));
+        assert!(rendered.contains("# TYPE requests_total counter\nrequests_total{code=\"200\"} 42"));
+        assert!(rendered.contains("# TYPE queue_depth untyped\n"));
+        assert!(!rendered.contains("# EOF"));
+    }
+
+    #[test]
+    fn render_openmetrics() {
+        let rendered = ExpositionFormat::OpenMetrics.render(&families());
+
+        assert_eq!(rendered, [
+            "# HELP router_status Status of the \\\"router\\\"",
+            "# TYPE router_status stateset",
+            "router_status{router_status=\"ok\"} 1",
+            "router_status{router_status=\"warning\"} 0",
+            "# HELP requests Requests served",
+            "# TYPE requests counter",
+            "requests_total{code=\"200\"} 42",
+            "# HELP queue_depth Depth of the queue",
+            "# TYPE queue_depth unknown",
+            "queue_depth 3",
+            "# HELP router Information about router",
+            "# TYPE router info",
+            "router_info{version=\"1.2\"} 1",
+            "# EOF\n"
+        ].join("\n"));
+    }
+}
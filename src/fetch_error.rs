@@ -0,0 +1,35 @@
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum FetchError {
+    HttpError(reqwest::Error),
+    SecretError(String),
+    BodyError(String),
+    SourceError(String)
+}
+
+impl FetchError {
+    /// Status code of an upstream that responded with an error
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            FetchError::HttpError(err) => err.status().map(|status| status.as_u16()),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::HttpError(e) => write!(f, "{}", e),
+            FetchError::SecretError(e) => write!(f, "{}", e),
+            FetchError::BodyError(e) => write!(f, "{}", e),
+            FetchError::SourceError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        FetchError::HttpError(err)
+    }
+}
@@ -0,0 +1,232 @@
+use convert_case::{Case, Casing};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{config_file::Flatten, prom_label::{self, PromLabel}, prom_metric::PromMetric};
+use crate::value_parser::ValueParser;
+
+/// Recursively walks a JSON document and emits every numeric or boolean leaf,
+/// and string leaves whose key has a value parser.
+/// Metric names are built from the key path, keys that look like ids
+/// become labels instead of name parts.
+pub struct FlattenProcessor<'a> {
+    config: &'a Flatten,
+    field_parsers: &'a Option<HashMap<String, ValueParser>>,
+    global_labels: &'a Option<Vec<PromLabel>>
+}
+
+impl<'a> FlattenProcessor<'a> {
+    pub fn new(config: &'a Flatten, field_parsers: &'a Option<HashMap<String, ValueParser>>, global_labels: &'a Option<Vec<PromLabel>>) -> Self {
+        Self {
+            config,
+            field_parsers,
+            global_labels
+        }
+    }
+
+    pub fn process(&self, json_value: &Value) -> Vec<PromMetric> {
+        let mut metrics = vec!();
+        let labels = self.global_labels.clone().unwrap_or_default();
+        self.visit(json_value, None, &[], &labels, 0, &mut metrics);
+        metrics
+    }
+
+    fn visit(&self,
+            json_value: &Value,
+            parent_key: Option<&str>,
+            name_parts: &[String],
+            labels: &[PromLabel],
+            depth: usize,
+            metrics: &mut Vec<PromMetric>) {
+        let exceeds_max_depth = self.config.max_depth.is_some_and(|max_depth| depth >= max_depth);
+
+        match json_value {
+            Value::Object(object) if !exceeds_max_depth => {
+                for (key, child) in object {
+                    self.visit_child(key, false, child, parent_key, name_parts, labels, depth, metrics);
+                }
+            },
+            Value::Array(array) if !exceeds_max_depth => {
+                for (index, child) in array.iter().enumerate() {
+                    self.visit_child(&index.to_string(), true, child, parent_key, name_parts, labels, depth, metrics);
+                }
+            },
+            Value::Number(_) | Value::String(_) if !name_parts.is_empty() && self.field_parser(parent_key).is_some() => {
+                if let Some(value) = self.field_parser(parent_key).and_then(|parser| parser.parse(json_value)) {
+                    metrics.push(self.metric(name_parts, value, labels));
+                }
+            },
+            Value::Number(_) | Value::Bool(_) if !name_parts.is_empty() => {
+                let value = json_value.as_f64().or_else(|| json_value.as_bool().map(|b| b as i64 as f64));
+                if let Some(value) = value {
+                    metrics.push(self.metric(name_parts, value, labels));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn metric(&self, name_parts: &[String], value: f64, labels: &[PromLabel]) -> PromMetric {
+        let metric_labels = if labels.is_empty() { None } else { Some(labels.to_vec()) };
+        PromMetric::new(name_parts.join(self.separator()), Some(value), metric_labels)
+    }
+
+    fn field_parser(&self, key: Option<&str>) -> Option<ValueParser> {
+        self.field_parsers.as_ref()?.get(key?).copied()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn visit_child(&self,
+            key: &str,
+            is_index: bool,
+            child: &Value,
+            parent_key: Option<&str>,
+            name_parts: &[String],
+            labels: &[PromLabel],
+            depth: usize,
+            metrics: &mut Vec<PromMetric>) {
+        if let Some(label_name) = self.id_label_name(key, is_index, parent_key) {
+            let mut child_labels = labels.to_vec();
+            child_labels.push(PromLabel::new(prom_label::unique_label_name(labels, label_name), key.to_string()));
+            self.visit(child, Some(key), name_parts, &child_labels, depth + 1, metrics);
+        } else {
+            let mut child_name_parts = name_parts.to_vec();
+            child_name_parts.push(key.to_case(Case::Snake));
+            self.visit(child, Some(key), &child_name_parts, labels, depth + 1, metrics);
+        }
+    }
+
+    /// Children of keys listed in `label_keys` always become labels.
+    /// Array indices and keys matching `id_pattern` become a label named after their parent key.
+    fn id_label_name(&self, key: &str, is_index: bool, parent_key: Option<&str>) -> Option<String> {
+        if let Some(label_name) = parent_key.and_then(|parent_key| self.config.label_keys.as_ref()?.get(parent_key)) {
+            return Some(label_name.to_string())
+        }
+
+        let looks_like_id = is_index || self.config.id_pattern.as_ref().is_some_and(|pattern| pattern.is_match(key));
+        if looks_like_id {
+            Some(parent_key.map_or("id".to_string(), |parent_key| parent_key.to_case(Case::Snake)))
+        } else {
+            None
+        }
+    }
+
+    fn separator(&self) -> &str {
+        self.config.separator.as_deref().unwrap_or("_")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::{config_file::Flatten, prom_label::PromLabel, prom_metric::PromMetric};
+    use crate::value_parser::ValueParser;
+    use super::FlattenProcessor;
+
+    fn flatten_config(yaml_str: &str) -> Flatten {
+        serde_yaml::from_str(yaml_str).unwrap()
+    }
+
+    fn json_document() -> serde_json::Value {
+        json!({
+            "uptime": 42,
+            "router": {
+                "status": "ok",
+                "up": true,
+                "backend": {
+                    "back1": { "healthy_count": 1, "load": 0.5 },
+                    "back2": { "healthy_count": 2, "load": 0.25 }
+                }
+            },
+            "nodes": {
+                "3fa85f64": { "cpu": 0.75 }
+            },
+            "queues": [
+                { "depth": 3 },
+                { "depth": 4 }
+            ]
+        })
+    }
+
+    fn sample(metric: &PromMetric) -> String {
+        let labels = metric.labels.iter()
+            .flatten()
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}{{{}}} {}", metric.name, labels, metric.value.unwrap())
+    }
+
+    fn flatten(config: &Flatten) -> Vec<String> {
+        let global_labels = Some(vec![PromLabel::new("environment".to_string(), "production".to_string())]);
+        let processor = FlattenProcessor::new(config, &None, &global_labels);
+        processor.process(&json_document()).iter().map(sample).collect()
+    }
+
+    #[test]
+    fn flatten_numeric_and_boolean_leaves() {
+        let samples = flatten(&flatten_config("{}"));
+
+        assert!(samples.contains(&"uptime{environment=\"production\"} 42".to_string()));
+        assert!(samples.contains(&"router_up{environment=\"production\"} 1".to_string()));
+        assert!(samples.contains(&"router_backend_back_1_healthy_count{environment=\"production\"} 1".to_string()));
+        assert!(!samples.iter().any(|s| s.starts_with("router_status")));
+    }
+
+    #[test]
+    fn flatten_array_indices_become_labels() {
+        let samples = flatten(&flatten_config("{}"));
+
+        assert!(samples.contains(&"queues_depth{environment=\"production\",queues=\"0\"} 3".to_string()));
+        assert!(samples.contains(&"queues_depth{environment=\"production\",queues=\"1\"} 4".to_string()));
+    }
+
+    #[test]
+    fn flatten_with_label_keys_and_id_pattern() {
+        let config = flatten_config(r#"
+label_keys:
+  backend: backend
+id_pattern: "^[0-9a-f]{8}$"
+"#);
+        let samples = flatten(&config);
+
+        assert!(samples.contains(&"router_backend_load{environment=\"production\",backend=\"back2\"} 0.25".to_string()));
+        assert!(samples.contains(&"nodes_cpu{environment=\"production\",nodes=\"3fa85f64\"} 0.75".to_string()));
+    }
+
+    #[test]
+    fn flatten_string_leaves_with_field_parsers() {
+        let config = flatten_config("{}");
+        let field_parsers = Some(vec![("heap".to_string(), ValueParser::Bytes)].into_iter().collect());
+        let document = json!({"jvm": {"heap": "2GiB", "version": "17.0.2"}});
+        let metrics = FlattenProcessor::new(&config, &field_parsers, &None).process(&document);
+
+        assert_eq!(metrics.iter().map(sample).collect::<Vec<_>>(), vec!["jvm_heap{} 2147483648"]);
+    }
+
+    #[test]
+    fn flatten_suffixes_id_labels_repeated_by_nested_levels() {
+        let config = flatten_config(r#"
+label_keys:
+  clusters: id
+  nodes: id
+"#);
+        let document = json!({"clusters": {"eu": {"nodes": {"n1": {"cpu": 0.5}}}}});
+        let metrics = FlattenProcessor::new(&config, &None, &None).process(&document);
+
+        assert_eq!(metrics.iter().map(sample).collect::<Vec<_>>(), vec!["clusters_nodes_cpu{id=\"eu\",id_2=\"n1\"} 0.5"]);
+    }
+
+    #[test]
+    fn flatten_with_separator_and_max_depth() {
+        let config = flatten_config(r#"
+separator: ":"
+max_depth: 2
+"#);
+        let samples = flatten(&config);
+
+        assert!(samples.contains(&"router:up{environment=\"production\"} 1".to_string()));
+        assert!(samples.contains(&"uptime{environment=\"production\"} 42".to_string()));
+        assert!(!samples.iter().any(|s| s.starts_with("router:backend")));
+    }
+}
@@ -0,0 +1,232 @@
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+/// Format of the fetched document. Everything but JSON gets converted to JSON
+/// before the conversion, so selectors work the same for every format
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    Json,
+    /// Newline delimited JSON, the documents become an array
+    Ndjson,
+    Yaml,
+    Toml,
+    Xml,
+    Csv
+}
+
+impl InputFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        match mime_type.as_str() {
+            "application/json" | "text/json" => Some(InputFormat::Json),
+            "application/x-ndjson" | "application/jsonl" => Some(InputFormat::Ndjson),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => Some(InputFormat::Yaml),
+            "application/toml" | "text/toml" => Some(InputFormat::Toml),
+            "application/xml" | "text/xml" => Some(InputFormat::Xml),
+            "text/csv" | "application/csv" => Some(InputFormat::Csv),
+            _ if mime_type.ends_with("+json") => Some(InputFormat::Json),
+            _ if mime_type.ends_with("+xml") => Some(InputFormat::Xml),
+            _ => None
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(InputFormat::Json),
+            "ndjson" | "jsonl" => Some(InputFormat::Ndjson),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            "toml" => Some(InputFormat::Toml),
+            "xml" => Some(InputFormat::Xml),
+            "csv" => Some(InputFormat::Csv),
+            _ => None
+        }
+    }
+
+    /// JSON documents are returned unchanged
+    pub fn to_json(self, document: String) -> Result<String, String> {
+        let value = match self {
+            InputFormat::Json => return Ok(document),
+            InputFormat::Ndjson => serde_json::Deserializer::from_str(&document)
+                .into_iter::<Value>()
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array)
+                .map_err(|err| err.to_string())?,
+            InputFormat::Yaml => serde_yaml::from_str::<Value>(&document).map_err(|err| err.to_string())?,
+            InputFormat::Toml => toml::from_str::<Value>(&document).map_err(|err| err.to_string())?,
+            InputFormat::Xml => xml_to_json(&document)?,
+            InputFormat::Csv => csv_to_json(&document)?
+        };
+        Ok(value.to_string())
+    }
+}
+
+/// The root element becomes the only key of the document.
+/// Attributes and child elements become keys of their element, repeated keys become arrays.
+/// Elements with nothing but text become scalars
+fn xml_to_json(document: &str) -> Result<Value, String> {
+    let xml = roxmltree::Document::parse(document).map_err(|err| err.to_string())?;
+    let root = xml.root_element();
+
+    let mut object = Map::new();
+    object.insert(root.tag_name().name().to_string(), xml_element_to_json(root));
+    Ok(Value::Object(object))
+}
+
+fn xml_element_to_json(element: roxmltree::Node) -> Value {
+    let mut object = Map::new();
+    for attribute in element.attributes() {
+        insert_or_push(&mut object, attribute.name(), infer_scalar(attribute.value()));
+    }
+    for child in element.children().filter(|child| child.is_element()) {
+        insert_or_push(&mut object, child.tag_name().name(), xml_element_to_json(child));
+    }
+
+    let text = element.children()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect::<String>();
+    let text = text.trim();
+
+    match (object.is_empty(), text.is_empty()) {
+        (true, _) => infer_scalar(text),
+        (false, false) => {
+            insert_or_push(&mut object, "text", infer_scalar(text));
+            Value::Object(object)
+        },
+        (false, true) => Value::Object(object)
+    }
+}
+
+fn insert_or_push(object: &mut Map<String, Value>, key: &str, value: Value) {
+    match object.get_mut(key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+        None => {
+            object.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// The header row names the fields, every other row becomes one object
+fn csv_to_json(document: &str) -> Result<Value, String> {
+    let mut reader = csv::Reader::from_reader(document.as_bytes());
+    let headers = reader.headers().map_err(|err| err.to_string())?.clone();
+
+    let mut rows = vec!();
+    for record in reader.records() {
+        let record = record.map_err(|err| err.to_string())?;
+        let row = headers.iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header.to_string(), infer_scalar(field)))
+            .collect::<Map<_, _>>();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// XML and CSV only know strings, numbers and booleans are recovered from them
+fn infer_scalar(text: &str) -> Value {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<i64>() {
+        return Value::Number(number.into())
+    }
+    if let Some(number) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+        return Value::Number(number)
+    }
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use super::InputFormat;
+
+    fn to_json(format: InputFormat, document: &str) -> Value {
+        serde_json::from_str(&format.to_json(document.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn format_from_content_type() {
+        assert_eq!(InputFormat::from_content_type("application/json; charset=utf-8"), Some(InputFormat::Json));
+        assert_eq!(InputFormat::from_content_type("application/vnd.api+json"), Some(InputFormat::Json));
+        assert_eq!(InputFormat::from_content_type("application/x-yaml"), Some(InputFormat::Yaml));
+        assert_eq!(InputFormat::from_content_type("text/xml;charset=UTF-8"), Some(InputFormat::Xml));
+        assert_eq!(InputFormat::from_content_type("text/csv"), Some(InputFormat::Csv));
+        assert_eq!(InputFormat::from_content_type("application/x-ndjson"), Some(InputFormat::Ndjson));
+        assert_eq!(InputFormat::from_content_type("text/plain"), None);
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(InputFormat::from_path("/var/run/app/status.yml"), Some(InputFormat::Yaml));
+        assert_eq!(InputFormat::from_path("/var/run/app/status.TOML"), Some(InputFormat::Toml));
+        assert_eq!(InputFormat::from_path("/var/log/app/status.jsonl"), Some(InputFormat::Ndjson));
+        assert_eq!(InputFormat::from_path("/var/run/app/status"), None);
+    }
+
+    #[test]
+    fn ndjson_to_json_array() {
+        let ndjson = "{\"name\": \"requests\", \"value\": 3}\n{\"name\": \"errors\", \"value\": 1}\n";
+        assert_eq!(to_json(InputFormat::Ndjson, ndjson), json!([{"name": "requests", "value": 3}, {"name": "errors", "value": 1}]));
+        assert_eq!(to_json(InputFormat::Ndjson, "{\"uptime\": 5}"), json!([{"uptime": 5}]));
+    }
+
+    #[test]
+    fn yaml_and_toml_to_json() {
+        let yaml = "router:\n  uplinks: 2\n  status: ok\n";
+        assert_eq!(to_json(InputFormat::Yaml, yaml), json!({"router": {"uplinks": 2, "status": "ok"}}));
+
+        let toml = "[router]\nuplinks = 2\nload = 0.5\n";
+        assert_eq!(to_json(InputFormat::Toml, toml), json!({"router": {"uplinks": 2, "load": 0.5}}));
+    }
+
+    #[test]
+    fn xml_to_json_merges_attributes_and_repeated_elements() {
+        let xml = r#"<?xml version="1.0"?>
+<status>
+  <jvm><memory free="1024" total="4096"/></jvm>
+  <connector name="http">
+    <threadInfo busy="2"/>
+  </connector>
+  <connector name="ajp">
+    <threadInfo busy="0"/>
+  </connector>
+  <healthy>true</healthy>
+  <version major="9">9.0.1</version>
+</status>"#;
+
+        assert_eq!(to_json(InputFormat::Xml, xml), json!({
+            "status": {
+                "jvm": {"memory": {"free": 1024, "total": 4096}},
+                "connector": [
+                    {"name": "http", "threadInfo": {"busy": 2}},
+                    {"name": "ajp", "threadInfo": {"busy": 0}}
+                ],
+                "healthy": true,
+                "version": {"major": 9, "text": "9.0.1"}
+            }
+        }));
+    }
+
+    #[test]
+    fn csv_to_json_returns_records() {
+        let csv = "name,value,host\nqueue_depth,3,mq-1\nqueue_load,0.25,mq-2\n";
+        assert_eq!(to_json(InputFormat::Csv, csv), json!([
+            {"name": "queue_depth", "value": 3, "host": "mq-1"},
+            {"name": "queue_load", "value": 0.25, "host": "mq-2"}
+        ]));
+    }
+
+    #[test]
+    fn invalid_documents_return_error() {
+        assert!(InputFormat::Xml.to_json("<status>".to_string()).is_err());
+        assert!(InputFormat::Yaml.to_json("router: [".to_string()).is_err());
+        assert_eq!(InputFormat::Json.to_json("not json".to_string()), Ok("not json".to_string()));
+    }
+}
@@ -1,61 +1,108 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use std::process::Stdio;
-use std::process::Output;
-use std::process::Command;
-use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use jaq_interpret::{Ctx, Filter, FilterT, ParseCtx, RcIter, Val};
+use serde_json::Value;
 
 use crate::utils;
 
+/// Evaluates `jq` filters in-process.
+/// Every filter gets compiled once and is cached by its selector,
+/// clones of an instance share the cache.
 #[derive(Clone)]
-pub struct Jq {}
+pub struct Jq {
+    std_defs: Arc<Vec<jaq_syn::Def>>,
+    filters: Arc<Mutex<HashMap<String, Arc<Filter>>>>
+}
 
 impl Jq {
-    pub fn new() -> Result<Self, std::io::Error> {
-        let jq = Self {};
-
-        if let Err(err) = jq.version() {
-            Err(err)
-        }
-        else {
-            Ok(jq)
+    pub fn new() -> Self {
+        Self {
+            std_defs: Arc::new(jaq_std::std()),
+            filters: Arc::default()
         }
     }
 
-    pub fn version(&self) -> Result<Output, std::io::Error> {
-        Command::new("jq")
-                .arg("--version")
-                .output()
+    /// Runs `jq_query` against an already parsed JSON document
+    /// and returns every value the filter yields
+    pub fn resolve(&self, json_value: &Value, jq_query: &str) -> Result<Vec<Value>, JqError> {
+        self.resolve_input(&JqInput::new(json_value), jq_query)
     }
 
-    pub fn resolve_raw(&self, json_payload: &str, jq_query: &str) -> Result<String, std::io::Error> {
-        let jq_process = Command::new("jq")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .arg(jq_query)
-            .spawn()?;
+    /// Same as `resolve`, for documents many filters run against
+    pub fn resolve_input(&self, input: &JqInput, jq_query: &str) -> Result<Vec<Value>, JqError> {
+        let filter = self.filter(jq_query)?;
+        let inputs = RcIter::new(core::iter::empty());
+        filter.run((Ctx::new([], &inputs), input.0.clone()))
+            .map(|result| result
+                .map(Value::from)
+                .map_err(|err| JqError::FilterError(ValueError::new(format!("Failed to apply filter {}: {}", jq_query, err)))))
+            .collect()
+    }
 
-        jq_process.stdin.unwrap().write_all(json_payload.as_bytes())?;
+    /// Converts the single value `jq_query` yields into its string representation.
+    /// Throws an error if the value is not a scalar
+    pub fn resolve_json_scalar_value(&self, input: &JqInput, jq_query: &str) -> Result<String, JqError> {
+        let values = self.resolve_input(input, jq_query)?;
+        match values.as_slice() {
+            [value] => utils::json_value_to_str(value).ok_or_else(|| {
+                JqError::NonScalarValueError(ValueError::new(format!("Expected scalar value. Found {}", value)))
+            }),
+            values => Err(JqError::NonScalarValueError(ValueError::new(format!("Expected a single scalar value. Found {} values", values.len()))))
+        }
+    }
 
-        let mut filtered_json = String::new();
-        jq_process.stdout.unwrap().read_to_string(&mut filtered_json)?;
+    /// Selectors come from the configuration, so the cache stays as small as the configuration
+    fn filter(&self, jq_query: &str) -> Result<Arc<Filter>, JqError> {
+        if let Some(filter) = self.filters.lock().unwrap().get(jq_query) {
+            return Ok(filter.clone())
+        }
 
-        Ok(filtered_json)
+        let filter = Arc::new(self.compile(jq_query)?);
+        self.filters.lock().unwrap().insert(jq_query.to_string(), filter.clone());
+        Ok(filter)
     }
 
-    /// Converts a JSON Value into an i64
-    /// Throws an error if the value cannot be converted to i64
-    pub fn resolve_json_scalar_value(&self, json_payload: &str, jq_query: &str) -> Result<String, JqError> {
-        let raw_json_value = self.resolve_raw(json_payload, jq_query)?;
-        let json_value : serde_json::Value = serde_json::from_str(&raw_json_value)?;
-        match utils::json_value_to_str(&json_value) {
-            Some(val) => Ok(val),
-            None => {
-                let err_msg = format!("Expected scalar value. Found {}", json_value.to_string()).to_string();
-                let err = ValueError { message: err_msg };
-                Err(JqError::NonScalarValueError(err))
-            }
+    fn compile(&self, jq_query: &str) -> Result<Filter, JqError> {
+        let mut defs = ParseCtx::new(Vec::new());
+        defs.insert_natives(jaq_core::core());
+        defs.insert_defs(self.std_defs.iter().cloned());
+
+        let (filter, errs) = jaq_parse::parse(jq_query, jaq_parse::main());
+        if let Some(err) = errs.first() {
+            return Err(JqError::FilterError(ValueError::new(format!("Failed to parse filter {}: {}", jq_query, err))));
+        }
+
+        let filter = defs.compile(filter.unwrap());
+        if let Some((err, _span)) = defs.errs.first() {
+            return Err(JqError::FilterError(ValueError::new(format!("Failed to compile filter {}: {}", jq_query, err))));
         }
+        Ok(filter)
+    }
+}
+
+/// A JSON document converted into the representation filters run against.
+/// Converting copies the whole document, clones of the input are cheap,
+/// so it gets converted once per scrape and shared by all selectors.
+pub struct JqInput(Val);
+
+impl JqInput {
+    pub fn new(json_value: &Value) -> Self {
+        Self(Val::from(json_value.clone()))
+    }
+}
+
+impl fmt::Debug for Jq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jq").finish_non_exhaustive()
+    }
+}
+
+impl Default for Jq {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -64,6 +111,14 @@ pub struct ValueError {
     pub message: String
 }
 
+impl ValueError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message
+        }
+    }
+}
+
 impl fmt::Display for ValueError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.message)
@@ -85,26 +140,101 @@ impl Error for ValueError {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum JqError {
-    IoError(std::io::Error),
-    ParserError(serde_json::Error),
+    FilterError(ValueError),
     NonScalarValueError(ValueError)
 }
 
-impl From<std::io::Error> for JqError {
-    fn from(io_err: std::io::Error) -> Self {
-        JqError::IoError(io_err)
+impl fmt::Display for JqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JqError::FilterError(e) => write!(f, "{}", e),
+            JqError::NonScalarValueError(e) => write!(f, "{}", e),
+        }
     }
 }
 
-impl From<serde_json::Error> for JqError {
-   fn from(serde_error: serde_json::Error) -> Self {
-       JqError::ParserError(serde_error)
-   }
-}
-
 impl From<ValueError> for JqError {
     fn from(value_err: ValueError) -> Self {
         JqError::NonScalarValueError(value_err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use super::{Jq, JqError, JqInput};
+    use assert_matches::assert_matches;
+
+    fn json_document() -> Value {
+        json!({
+            "id": "xyz",
+            "components": {
+                "router": {
+                    "status": "Warning",
+                    "num_uplinks": 2
+                }
+            },
+            "items": [1, 2, 3]
+        })
+    }
+
+    #[test]
+    fn resolve_returns_object() {
+        let jq = Jq::new();
+        let values = jq.resolve(&json_document(), ".components.router").unwrap();
+        assert_eq!(values[0]["num_uplinks"], 2);
+    }
+
+    #[test]
+    fn resolve_one_value_per_result() {
+        let jq = Jq::new();
+        let values = jq.resolve(&json_document(), ".items[] | select(. > 1)").unwrap();
+        assert_eq!(values, vec![json!(2), json!(3)]);
+    }
+
+    #[test]
+    fn resolve_input_runs_several_filters_against_one_conversion() {
+        let jq = Jq::new();
+        let input = JqInput::new(&json_document());
+        assert_eq!(jq.resolve_input(&input, ".items | length").unwrap(), vec![json!(3)]);
+        assert_eq!(jq.resolve_input(&input, ".components.router.num_uplinks").unwrap(), vec![json!(2)]);
+    }
+
+    #[test]
+    fn resolve_missing_key_returns_null() {
+        let jq = Jq::new();
+        assert_eq!(jq.resolve(&json_document(), ".does_not_exist").unwrap(), vec![Value::Null]);
+    }
+
+    #[test]
+    fn resolve_invalid_filter_returns_error() {
+        let jq = Jq::new();
+        assert_matches!(jq.resolve(&json_document(), ".components |||"), Err(JqError::FilterError(_)));
+        assert_matches!(jq.resolve(&json_document(), "undefined_function"), Err(JqError::FilterError(_)));
+    }
+
+    #[test]
+    fn resolve_reuses_compiled_filters() {
+        let jq = Jq::new();
+        let clone = jq.clone();
+        jq.resolve(&json_document(), ".id").unwrap();
+        assert_eq!(clone.resolve(&json!({"id": "abc"}), ".id").unwrap(), vec![json!("abc")]);
+        assert_eq!(jq.filters.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn resolve_json_scalar_value_returns_string() {
+        let jq = Jq::new();
+        assert_eq!(jq.resolve_json_scalar_value(&JqInput::new(&json_document()), ".id").unwrap(), "xyz");
+    }
+
+    #[test]
+    fn resolve_json_scalar_value_rejects_objects() {
+        let jq = Jq::new();
+        assert_matches!(jq.resolve_json_scalar_value(&JqInput::new(&json_document()), ".components"), Err(JqError::NonScalarValueError(_)));
+        assert_matches!(jq.resolve_json_scalar_value(&JqInput::new(&json_document()), ".does_not_exist"), Err(JqError::NonScalarValueError(_)));
+        assert_matches!(jq.resolve_json_scalar_value(&JqInput::new(&json_document()), ".items[]"), Err(JqError::NonScalarValueError(_)));
+    }
+}
@@ -1,6 +1,6 @@
 use convert_case::{Case, Casing};
 use serde_json::{Map, Value};
-use crate::{config_file::ConfigFile, prom_label::PromLabel, prom_metric::PromMetric, utils};
+use crate::{config_file::{FieldMode, Module}, payload_error::PayloadError, prom_label::PromLabel, prom_metric::{MetricType, PromMetric}, utils};
 
 pub struct JsonObjectProcessor {
     root_key_name: String,
@@ -16,28 +16,99 @@ impl JsonObjectProcessor {
             Self {
                 root_key_name: root_key_name.to_case(Case::Snake),
                 child_object: child_object_map.clone(),
-                global_labels: global_labels
+                global_labels
             }
         )
     }
 
-    pub fn visit(&self, config: &ConfigFile) -> Option<Vec<PromMetric>> {
-        if config.gauge_field_values.is_some() {
-            return self.multi_metric_strategy(config)
+    /// Fails when a gauge field with `gauge_field_values` holds no scalar value
+    pub fn visit(&self, config: &Module) -> Result<Option<Vec<PromMetric>>, PayloadError> {
+        let mut metrics = self.state_set_metrics(config);
+        let gauge_metrics = if config.state_set(&config.gauge_field).is_some() {
+            None
+        } else if config.gauge_field_values.is_some() {
+            self.multi_metric_strategy(config)?
+        } else {
+            self.single_metric_strategy(config).map(|metric| vec!(metric))
+        };
+        metrics.extend(gauge_metrics.into_iter().flatten());
+        metrics.extend(self.field_metrics(config));
+        metrics.extend(self.info_metric(config));
+
+        Ok(Some(metrics).filter(|metrics| !metrics.is_empty()))
+    }
+
+    /// Every field with a state set, the gauge field included, gets reported as a state set
+    fn state_set_metrics(&self, config: &Module) -> Vec<PromMetric> {
+        let labels = self.object_labels(config);
+
+        let mut metrics = vec!();
+        for field in self.child_object.iter() {
+            if let Some(state_set) = config.state_set(field.0) {
+                for (state, value) in state_set.samples(&self.metric_name(field), utils::json_value_to_str(field.1).as_deref()) {
+                    let mut state_labels = labels.clone();
+                    state_labels.push(PromLabel::new(field.0.to_case(Case::Snake), state));
+                    metrics.push(PromMetric::new(self.metric_name(field), Some(value), Some(state_labels))
+                        .with_type(MetricType::StateSet));
+                }
+            }
         }
+        metrics
+    }
 
-        if let Some(metric) = self.single_metric_strategy(config) {
-            Some(vec!(metric))
-        } else {
-            None
+    /// Fields in `metric` mode, labeled like the gauge field
+    fn field_metrics(&self, config: &Module) -> Vec<PromMetric> {
+        let labels = self.object_labels(config);
+        self.sibling_fields(config, FieldMode::Metric)
+            .filter_map(|field| {
+                let prom_value = utils::parse_json_value(field.1, config.field_parser(field.0), config.value_mappings.as_ref())?;
+                Some(PromMetric::new(self.metric_name(field), Some(prom_value), Some(labels.clone())))
+            })
+            .collect()
+    }
+
+    /// `<object>_info` with value 1, labeled with every field in `info` mode
+    fn info_metric(&self, config: &Module) -> Option<PromMetric> {
+        let mut info_labels = self.sibling_fields(config, FieldMode::Info)
+            .filter_map(|field| Some(PromLabel::new(field.0.to_case(Case::Snake), utils::json_value_to_str(field.1)?)))
+            .collect::<Vec<_>>();
+        if info_labels.is_empty() {
+            return None
         }
+
+        let mut labels = self.object_labels(config);
+        labels.append(&mut info_labels);
+        Some(PromMetric::new(format!("{}_info", self.root_key_name), Some(1.0), Some(labels))
+            .with_type(MetricType::Info)
+            .with_help(Some(format!("Information about {} converted from JSON", self.root_key_name))))
+    }
+
+    /// Global labels followed by the fields in `label` mode
+    fn object_labels(&self, config: &Module) -> Vec<PromLabel> {
+        let mut labels = self.global_labels.clone().unwrap_or_default();
+        labels.append(&mut self.extract_labels(config, &self.child_object));
+        labels
     }
 
-    fn multi_metric_strategy(&self, config: &ConfigFile) -> Option<Vec<PromMetric>> {
+    /// Scalar fields besides the gauge field and state sets, reported in `field_mode`
+    fn sibling_fields<'a>(&'a self, config: &'a Module, field_mode: FieldMode) -> impl Iterator<Item = (&'a String, &'a Value)> {
+        self.child_object.iter()
+            .filter(move |(name, value)| {
+                name.as_str() != config.gauge_field
+                    && config.state_set(name).is_none()
+                    && (value.is_number() || value.is_string() || value.is_boolean())
+                    && config.field_mode(name, value) == field_mode
+            })
+    }
+
+    fn multi_metric_strategy(&self, config: &Module) -> Result<Option<Vec<PromMetric>>, PayloadError> {
         let gauge_field_name = config.gauge_field.to_string();
         let mut labels = vec!();
         labels.append(&mut self.extract_labels(config, &self.child_object));
-        let gauge_field = self.child_object.iter().find(|(name, _value)| name.to_string().eq(&gauge_field_name))?;
+        let gauge_field = match self.child_object.iter().find(|(name, _value)| name.to_string().eq(&gauge_field_name)) {
+            Some(gauge_field) => gauge_field,
+            None => return Ok(None)
+        };
         let mut metrics = vec!();
         let metric_labels = self.generate_metric_labels(labels);
         if let Some(gauge_field_values) = &config.gauge_field_values {
@@ -45,42 +116,41 @@ impl JsonObjectProcessor {
                             gauge_field_name,
                             gauge_field,
                             metric_labels,
-                            gauge_field_values)
+                            gauge_field_values).map(Some)
         }
-        else {
-            let prom_value = utils::json_value_to_i64(gauge_field.1)?;
+        else if let Some(prom_value) = utils::parse_json_value(gauge_field.1, config.field_parser(&gauge_field_name), config.value_mappings.as_ref()) {
             metrics.push(PromMetric::new(self.metric_name(gauge_field), Some(prom_value), metric_labels));
         }
 
-        Some(metrics)
+        Ok(Some(metrics))
     }
 
     fn generate_metric_labels(&self, mut labels: Vec<PromLabel>) -> Option<Vec<PromLabel>> {
-        let metric_labels = if labels.len() > 0 && self.global_labels.is_some() {
+        if !labels.is_empty() && self.global_labels.is_some() {
             let mut l = self.global_labels.clone().unwrap();
             l.append(&mut labels);
             Some(l)
         } else {
             self.global_labels.clone()
-        };
-        metric_labels
+        }
     }
 
-    fn single_metric_strategy(&self, config: &ConfigFile) -> Option<PromMetric> {
+    fn single_metric_strategy(&self, config: &Module) -> Option<PromMetric> {
         let gauge_config_field_name = config.gauge_field.to_string();
         let mut labels = vec!();
         labels.append(&mut self.extract_labels(config, &self.child_object));
         let gauge_field = self.child_object.iter().find(|(name, _value)| name.to_string().eq(&gauge_config_field_name))?;
-        let prom_value = utils::json_value_to_i64(gauge_field.1)?;
+        let prom_value = utils::parse_json_value(gauge_field.1, config.field_parser(&gauge_config_field_name), config.value_mappings.as_ref())?;
         let metric_labels = self.generate_metric_labels(labels);
         Some(PromMetric::new(self.metric_name(gauge_field), Some(prom_value), metric_labels))
     }
 
-    fn extract_labels(&self, config: &ConfigFile, child_object: &serde_json::Map<String, Value>) -> Vec<PromLabel> {
+    fn extract_labels(&self, config: &Module, child_object: &serde_json::Map<String, Value>) -> Vec<PromLabel> {
         let gauge_field = config.gauge_field.to_string();
         let mut labels = vec!(); //Vec<PromLabel>;
-        for child_key in child_object.iter().filter(|kv| kv.0.ne(&gauge_field)) {
-            if child_key.1.is_number() || child_key.1.is_string() || child_key.1.is_boolean() {
+        for child_key in child_object.iter().filter(|kv| kv.0.ne(&gauge_field) && config.state_set(kv.0).is_none()) {
+            if (child_key.1.is_number() || child_key.1.is_string() || child_key.1.is_boolean())
+                && config.field_mode(child_key.0, child_key.1) == FieldMode::Label {
                 let label_name = child_key.0.to_case(Case::Snake);
                 if let Some(prom_value) = utils::json_value_to_str(child_key.1) {
                     labels.push(PromLabel::new(label_name, prom_value));
@@ -99,9 +169,14 @@ impl JsonObjectProcessor {
                 gauge_field_name: String,
                 gauge_field: (&String, &Value),
                 metric_labels: Option<Vec<PromLabel>>,
-                gauge_field_values: &Vec<String>) -> Option<Vec<PromMetric>> {
-
-        Some(gauge_field_values.iter()
+                gauge_field_values: &[String]) -> Result<Vec<PromMetric>, PayloadError> {
+
+        let converted_value = utils::json_value_to_str(gauge_field.1).ok_or_else(|| PayloadError::NonScalarValueError(format!(
+            "Key {} of {} is not a scalar value",
+            gauge_field_name,
+            self.root_key_name
+        )))?;
+        Ok(gauge_field_values.iter()
             .map(|field_value| {
                 let labels = match metric_labels.clone() {
                     Some(mut labels) => {
@@ -111,12 +186,11 @@ impl JsonObjectProcessor {
                     None => None
                 };
 
-                let converted_value= utils::json_value_to_str(gauge_field.1).unwrap();
                 if converted_value.to_lowercase() == field_value.to_lowercase() {
-                    PromMetric::new(self.metric_name(gauge_field).to_string(), Some(1), labels)
+                    PromMetric::new(self.metric_name(gauge_field).to_string(), Some(1.0), labels)
                 }
                 else {
-                    PromMetric::new(self.metric_name(gauge_field).to_string(), Some(0), labels)
+                    PromMetric::new(self.metric_name(gauge_field).to_string(), Some(0.0), labels)
                 }
             })
             .collect::<Vec<_>>())
@@ -1,100 +1,340 @@
-use crate::config_file::ConfigFile;
+use crate::config_file::{DEFAULT_MODULE, Module};
+use crate::config_reloader::ConfigReloader;
+use crate::exposition::{ExpositionFormat, MetricFamily};
+use crate::poll_cache::PollCache;
+use crate::rule_error::{RuleError, RuleErrorCounter};
 use clap::{AppSettings, Clap};
 use exporter::Exporter;
-use rocket::http::Status;
+use input_format::InputFormat;
+use source::Source;
+use payload_error::PayloadError;
+use scrape_error::ScrapeError;
+use scrape_result::{ScrapeResult, ScrapeStats};
+use scrape_timeout::ScrapeTimeout;
+use rocket::http::{ContentType, Status};
 use rocket::response::{content, status};
+use rocket::State;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[macro_use] extern crate rocket;
 
 mod payload;
 mod config_file;
+mod config_reloader;
 mod prom_metric;
 mod prom_label;
+mod prom_name;
 mod jq;
 mod utils;
 mod selector_error;
 mod payload_error;
 mod json_object_processor;
 mod custom_include;
+mod flatten_processor;
+mod metric_mapping;
+mod distribution;
+mod record_processor;
 mod exporter;
+mod http_client;
+mod source;
+mod input_format;
+mod fetch_error;
+mod tls;
+mod probe_result;
+mod scrape_error;
+mod scrape_result;
+mod scrape_timeout;
+mod poll_cache;
+mod rule_error;
+mod value_parser;
+mod exposition;
 
-#[derive(Clap)]
+#[derive(Clap, Clone)]
 #[clap(version = "1.0", author = "Epsagon")]
 #[clap(setting = AppSettings::ColoredHelp)]
 struct Opts {
-    json_endpoint: String,
+    // Endpoint scraped by `/metrics`: an HTTP URL, `file://<path>` or `exec:<command>`. Optional when only `/probe` is used
+    json_endpoint: Option<String>,
 
     // Path to overrides yaml file. Optional
     #[clap(short='c', long="config", value_name="File")]
     overrides: String,
 
+    // Used for every module that doesn't define its own `entry_point`
     #[clap(short='e', long="entrypoint", value_name="Entry Point in jq notation (e.g. \".components\")")]
-    entry_point: Option<String>
+    entry_point: Option<String>,
+
+    // Seconds between checks of the config file for changes, 0 disables watching
+    #[clap(long="config-check-interval", value_name="Seconds", default_value="5")]
+    config_check_interval: u64,
+
+    // Seconds subtracted from the scrape timeout Prometheus sends
+    #[clap(long="timeout-offset", value_name="Seconds", default_value="0.5")]
+    timeout_offset: f64,
+
+    // Seconds between background polls of the JSON endpoint. When set, `/metrics` serves the last polled result
+    #[clap(long="poll-interval", value_name="Seconds")]
+    poll_interval: Option<u64>,
+
+    // Seconds after which a polled result is reported as failed, defaults to three poll intervals
+    #[clap(long="poll-max-age", value_name="Seconds")]
+    poll_max_age: Option<u64>
 }
 
-async fn fetch_json(json_endpoint: String) -> Result<String, reqwest::Error> {
-    let res = reqwest::get(json_endpoint).await?;
-    let body = res.text().await?;
-    Ok(body)
+impl Opts {
+    fn entry_point(&self, module: &Module) -> String {
+        module.entry_point.clone()
+            .or_else(|| self.entry_point.clone())
+            .unwrap_or_else(|| ".".to_string())
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval.filter(|interval| *interval > 0).map(Duration::from_secs)
+    }
+
+    fn poll_max_age(&self) -> Duration {
+        self.poll_max_age.map(Duration::from_secs)
+            .or_else(|| self.poll_interval().map(|interval| interval * 3))
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the metrics along with the rules that failed and got skipped
+fn process_json(config: &Module, json_entry_point: String, body: String, deadline: Instant) -> Result<(Vec<MetricFamily>, Vec<RuleError>), PayloadError> {
+    let json_payload = payload::Payload::new(body, Some(json_entry_point), config).with_deadline(deadline);
+    let converted_metrics = json_payload.json_to_metrics()?;
+    let exporter = Exporter::new(config, converted_metrics);
+    let families = exporter.metric_families();
+    let mut rule_errors = json_payload.rule_errors();
+    rule_errors.append(&mut exporter.rule_errors());
+    Ok((families, rule_errors))
 }
 
-fn process_json(config_file_path: &str, json_entry_point: String, body: String) -> Option<String> {
-    let config = ConfigFile::from_file(config_file_path).unwrap();
-    let json_payload = payload::Payload::new(body, Some(json_entry_point), &config);
-    if let Ok(converted_metrics) = json_payload.json_to_metrics() {
-        let exporter = Exporter::new(&config, converted_metrics);
-        Some(exporter.generate_metrics())
+/// Fetches and converts the document within `timeout`.
+/// The conversion runs on a blocking thread and stops at the deadline as well.
+/// The returned stats describe the scrape, whether it succeeded or not
+async fn fetch_and_convert(module: &Module, entry_point: String, source: Source<'_>, timeout: Duration) -> (Result<Vec<MetricFamily>, ScrapeError>, ScrapeStats) {
+    let start = Instant::now();
+    let deadline = start + timeout;
+    let mut stats = ScrapeStats::default();
+    let scrape = async {
+        let document = source.fetch(module).await?;
+        stats.http_status = document.http_status;
+        stats.response_size = Some(document.body.len());
+        let format = module.format.or(document.format).unwrap_or(InputFormat::Json);
+        let module = module.clone();
+        let conversion = move || -> Result<_, ScrapeError> {
+            let body = format.to_json(document.body).map_err(ScrapeError::FormatError)?;
+            Ok(process_json(&module, entry_point, body, deadline)?)
+        };
+        let (metrics, rule_errors) = match tokio::task::spawn_blocking(conversion).await {
+            Ok(converted) => converted?,
+            Err(err) => std::panic::resume_unwind(err.into_panic())
+        };
+        stats.rule_errors = rule_errors;
+        Ok(metrics)
+    };
+
+    let result = tokio::time::timeout(timeout, scrape).await.unwrap_or(Err(ScrapeError::TimeoutError));
+    stats.duration = start.elapsed();
+    if let Err(ScrapeError::FetchError(err)) = &result {
+        stats.http_status = err.http_status();
     }
-    else {
-        None
+    stats.error_phase = result.as_ref().err().map(ScrapeError::phase);
+    (result, stats)
+}
+
+/// Metrics in the negotiated exposition format, or a plain text error
+type MetricsResponse = status::Custom<content::Custom<String>>;
+
+fn plain_response(status: Status, message: String) -> MetricsResponse {
+    status::Custom(status, content::Custom(ContentType::Plain, message))
+}
+
+fn metrics_response(format: ExpositionFormat, families: &[MetricFamily]) -> MetricsResponse {
+    status::Custom(Status::Ok, content::Custom(format.content_type(), format.render(families)))
+}
+
+fn load_module(config: &ConfigReloader, module_name: &str) -> Result<Module, String> {
+    config.config().module(module_name).cloned().ok_or_else(|| format!("Unknown module {}\n", module_name))
+}
+
+async fn scrape(opts: &Opts, config: &ConfigReloader, cache: &PollCache, rule_errors: &RuleErrorCounter, module_name: &str, scrape_timeout: ScrapeTimeout, format: ExpositionFormat) -> MetricsResponse {
+    let module = match load_module(config, module_name) {
+        Ok(module) => module,
+        Err(error_message) => return plain_response(Status::BadRequest, error_message)
+    };
+    let entry_point = opts.entry_point(&module);
+    let json_endpoint = match &opts.json_endpoint {
+        Some(json_endpoint) => json_endpoint,
+        None => {
+            let error_message = "No JSON endpoint configured, use /probe?target=<endpoint> instead\n".to_string();
+            return plain_response(Status::NotFound, error_message)
+        }
+    };
+
+    if opts.poll_interval().is_some() {
+        let mut families = cache.get(module_name).into_metric_families();
+        families.push(rule_errors.metrics(module_name));
+        families.append(&mut config.metrics());
+        return metrics_response(format, &families)
+    }
+
+    let timeout = scrape_timeout.duration(module.timeout, opts.timeout_offset);
+    let (result, stats) = fetch_and_convert(&module, entry_point, Source::new(json_endpoint), timeout).await;
+    if let Err(err) = &result {
+        eprintln!("ERR scraping {}: {}", json_endpoint, err);
     }
+    rule_errors.record(module_name, &stats.rule_errors);
+
+    let scrape_result = ScrapeResult::new(result.ok(), Some(stats));
+    let mut families = scrape_result.into_metric_families();
+    families.push(rule_errors.metrics(module_name));
+    families.append(&mut config.metrics());
+    metrics_response(format, &families)
 }
 
-#[get("/metrics")]
-async fn metrics() -> status::Custom<content::Plain<String>> {
-    let opts: Opts = Opts::parse();
-    let entry_point = opts.entry_point.unwrap_or(".".to_string());
-
-    match fetch_json(opts.json_endpoint.to_string()).await {
-        Ok(body) => {
-            let error_message = format!("Endpoint {} provided invalid JSON\n", opts.json_endpoint);
-            process_json(&opts.overrides, entry_point, body).map_or(status::Custom(Status::InternalServerError, content::Plain(error_message)),
-                |metrics| status::Custom(Status::Ok, content::Plain(metrics)))
-        },
+#[get("/metrics?<module>")]
+async fn metrics(module: Option<String>, opts: &State<Opts>, config: &State<Arc<ConfigReloader>>, cache: &State<Arc<PollCache>>, rule_errors: &State<Arc<RuleErrorCounter>>, scrape_timeout: ScrapeTimeout, format: ExpositionFormat) -> MetricsResponse {
+    scrape(opts, config, cache, rule_errors, module.as_deref().unwrap_or(DEFAULT_MODULE), scrape_timeout, format).await
+}
+
+#[get("/metrics/<module>")]
+async fn module_metrics(module: String, opts: &State<Opts>, config: &State<Arc<ConfigReloader>>, cache: &State<Arc<PollCache>>, rule_errors: &State<Arc<RuleErrorCounter>>, scrape_timeout: ScrapeTimeout, format: ExpositionFormat) -> MetricsResponse {
+    scrape(opts, config, cache, rule_errors, &module, scrape_timeout, format).await
+}
+
+#[get("/probe?<target>&<module>")]
+async fn probe(target: String, module: Option<String>, opts: &State<Opts>, config: &State<Arc<ConfigReloader>>, scrape_timeout: ScrapeTimeout, format: ExpositionFormat) -> MetricsResponse {
+    let module = match load_module(config, module.as_deref().unwrap_or(DEFAULT_MODULE)) {
+        Ok(module) => module,
+        Err(error_message) => return plain_response(Status::BadRequest, error_message)
+    };
+    let entry_point = opts.entry_point(&module);
+
+    let source = Source::new(&target);
+    if source.is_local() {
+        return plain_response(Status::BadRequest, "Only HTTP targets can be probed\n".to_string())
+    }
+    let timeout = scrape_timeout.duration(module.timeout, opts.timeout_offset);
+
+    let (result, stats) = fetch_and_convert(&module, entry_point, source, timeout).await;
+    let probe_result = probe_result::ProbeResult::new(result.ok(), stats.duration);
+
+    metrics_response(format, &probe_result.into_metric_families())
+}
+
+#[post("/-/reload")]
+fn reload(config: &State<Arc<ConfigReloader>>) -> status::Custom<content::Plain<String>> {
+    match config.reload() {
+        Ok(()) => status::Custom(Status::Ok, content::Plain("Config reloaded\n".to_string())),
         Err(err) => {
-            if err.is_timeout() || err.is_connect() {
-                status::Custom(Status::GatewayTimeout, content::Plain(err.to_string()))
-            }
-            else {
-                status::Custom(Status::InternalServerError, content::Plain(err.to_string()))
-            }
+            eprintln!("ERR while reloading config file: {}", err);
+            status::Custom(Status::InternalServerError, content::Plain(format!("Failed to reload config: {}\n", err)))
         }
     }
 }
 
-fn validate_config_file(opts: &Opts) {
-    if let Err(err) = ConfigFile::validate_config_file(&opts.overrides) {
-        eprintln!("ERR while loading config file: {:?}", err);
-        std::process::exit(1)
+fn log_reload_result(trigger: &str, result: Result<(), config_file::ConfigError>) {
+    match result {
+        Ok(()) => println!("config reloaded ({})", trigger),
+        Err(err) => eprintln!("ERR while reloading config file ({}): {}", trigger, err)
     }
 }
 
-fn check_jq_exists() {
-    if let Err(err) = jq::Jq::new() {
-        eprintln!("{}", err);
-        std::process::exit(1)
+fn watch_sighup(config: Arc<ConfigReloader>) {
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => return eprintln!("ERR while installing SIGHUP handler: {}", err)
+        };
+        while hangup.recv().await.is_some() {
+            log_reload_result("SIGHUP", config.reload());
+        }
+    });
+}
+
+fn watch_config_file(config: Arc<ConfigReloader>, interval: u64) {
+    if interval == 0 {
+        return
     }
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            ticks.tick().await;
+            if let Some(result) = config.reload_if_changed() {
+                log_reload_result("file change", result);
+            }
+        }
+    });
+}
+
+/// Converts every module in the background, `/metrics` serves the cached results
+fn poll_json_endpoint(opts: Opts, config: Arc<ConfigReloader>, cache: Arc<PollCache>, rule_errors: Arc<RuleErrorCounter>) {
+    let (json_endpoint, interval) = match (opts.json_endpoint.clone(), opts.poll_interval()) {
+        (Some(json_endpoint), Some(interval)) => (json_endpoint, interval),
+        _ => return
+    };
+
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let config_file = config.config();
+            cache.retain_modules(&config_file.modules.keys().collect::<Vec<_>>());
+
+            let polls = config_file.modules.iter().map(|(module_name, module)| {
+                let (module_name, module, json_endpoint) = (module_name.clone(), module.clone(), json_endpoint.clone());
+                let (cache, rule_errors) = (cache.clone(), rule_errors.clone());
+                let entry_point = opts.entry_point(&module);
+                let timeout = ScrapeTimeout::default().duration(module.timeout, opts.timeout_offset);
+                tokio::spawn(async move {
+                    let (result, stats) = fetch_and_convert(&module, entry_point, Source::new(&json_endpoint), timeout).await;
+                    if let Err(err) = &result {
+                        eprintln!("ERR polling {} for module {}: {}", json_endpoint, module_name, err);
+                    }
+                    rule_errors.record(&module_name, &stats.rule_errors);
+                    cache.update(&module_name, result.ok(), stats);
+                })
+            }).collect::<Vec<_>>();
+
+            for poll in polls {
+                let _ = poll.await;
+            }
+        }
+    });
+}
+
+fn validate_config_file(opts: &Opts) -> ConfigReloader {
+    ConfigReloader::new(&opts.overrides).unwrap_or_else(|err| {
+        eprintln!("ERR while loading config file: {}", err);
+        std::process::exit(1)
+    })
 }
 
 #[rocket::main]
+#[allow(clippy::result_large_err)]
 async fn main() -> Result<(), rocket::Error> {
     let opts: Opts = Opts::parse();
-    println!("reading {}", opts.json_endpoint);
-    validate_config_file(&opts);
-    check_jq_exists();
+    if let Some(json_endpoint) = &opts.json_endpoint {
+        println!("reading {}", json_endpoint);
+    }
+    let config = Arc::new(validate_config_file(&opts));
+    watch_sighup(config.clone());
+    watch_config_file(config.clone(), opts.config_check_interval);
+    let cache = Arc::new(PollCache::new(opts.poll_max_age()));
+    let rule_errors = Arc::new(RuleErrorCounter::default());
+    poll_json_endpoint(opts.clone(), config.clone(), cache.clone(), rule_errors.clone());
 
     rocket::build()
-    .mount("/", routes![metrics])
+    .manage(opts)
+    .manage(config)
+    .manage(cache)
+    .manage(rule_errors)
+    .mount("/", routes![metrics, module_metrics, probe, reload])
     .launch()
     .await
 }
\ No newline at end of file
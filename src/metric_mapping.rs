@@ -0,0 +1,128 @@
+use serde_json::Value;
+
+use crate::config_file::{MetricMapping, Module};
+use crate::distribution;
+use crate::jq::{Jq, JqInput};
+use crate::prom_label::PromLabel;
+use crate::payload_error::PayloadError;
+use crate::prom_metric::{MetricType, PromMetric};
+use crate::rule_error::RuleErrors;
+use crate::selector_error::SelectorError;
+use crate::utils;
+
+/// Converts the explicit `metrics` entries of a module.
+/// Every selector is evaluated against the payload document.
+pub struct MetricMappingProcessor<'a> {
+    config: &'a Module,
+    jq: &'a Jq,
+    json_document: &'a JqInput,
+    global_labels: &'a Option<Vec<PromLabel>>
+}
+
+impl<'a> MetricMappingProcessor<'a> {
+    pub fn new(
+        config: &'a Module,
+        jq: &'a Jq,
+        json_document: &'a JqInput,
+        global_labels: &'a Option<Vec<PromLabel>>
+    ) -> Self {
+        Self {
+            config,
+            jq,
+            json_document,
+            global_labels
+        }
+    }
+
+    /// Every mapping is a rule of its own, see `RuleErrors`
+    pub fn process(&self, rule_errors: &RuleErrors) -> Result<Vec<PromMetric>, PayloadError> {
+        let mut metrics = vec!();
+
+        for mapping in self.config.metrics.iter().flatten() {
+            let rule = format!("metric:{}", mapping.name);
+            if let Some(mut mapping_metrics) = rule_errors.isolate(&rule, self.mapping_to_metrics(mapping))? {
+                metrics.append(&mut mapping_metrics);
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    /// A value selector may yield several values, one sample (or histogram or summary) gets created for each of them.
+    /// Label selectors yield either a single scalar value shared by all samples or one scalar value per sample.
+    fn mapping_to_metrics(&self, mapping: &MetricMapping) -> Result<Vec<PromMetric>, SelectorError> {
+        let values = self.resolve(&mapping.value)?;
+        let mut label_values = vec!();
+
+        for (label_name, label_selector) in mapping.labels.iter().flatten() {
+            let resolved = self.resolve(label_selector)?
+                .iter()
+                .map(|value| utils::json_value_to_str(value).ok_or_else(|| SelectorError::non_scalar_value(&format!(
+                    "Label selector {} of metric {} yields {}, expected a scalar value",
+                    label_selector, mapping.name, value
+                ))))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if resolved.len() != 1 && resolved.len() != values.len() {
+                let message = format!(
+                    "Label selector {} of metric {} yields {} values, expected 1 or {}",
+                    label_selector, mapping.name, resolved.len(), values.len()
+                );
+                return Err(SelectorError::new(&message, None))
+            }
+            label_values.push((label_name, resolved));
+        }
+
+        let mut metrics = vec!();
+        for (index, value) in values.iter().enumerate() {
+            let mut labels = self.global_labels.clone().unwrap_or_default();
+            for (label_name, resolved) in &label_values {
+                let label_value = if resolved.len() == 1 { &resolved[0] } else { &resolved[index] };
+                labels.push(PromLabel::new(label_name.to_string(), label_value.to_string()));
+            }
+            metrics.append(&mut self.value_to_metrics(mapping, value, labels)?);
+        }
+
+        Ok(metrics)
+    }
+
+    /// Histograms and summaries are assembled from the keys of an object, anything else is a single sample.
+    /// `null` yields no sample, objects, arrays and strings that can't be parsed are errors
+    fn value_to_metrics(&self, mapping: &MetricMapping, value: &Value, labels: Vec<PromLabel>) -> Result<Vec<PromMetric>, SelectorError> {
+        let metric_type = mapping.metric_type.unwrap_or(MetricType::Gauge);
+        let distribution = mapping.distribution.clone().unwrap_or_default();
+        let samples = match metric_type {
+            MetricType::Histogram => distribution::histogram_samples(value, &distribution),
+            MetricType::Summary => distribution::summary_samples(value, &distribution),
+            _ if value.is_null() => return Ok(vec!()),
+            _ if value.is_object() || value.is_array() => return Err(SelectorError::non_scalar_value(&format!(
+                "Value selector {} of metric {} yields {}, expected a scalar value",
+                mapping.value, mapping.name, value
+            ))),
+            _ => {
+                let metric_value = utils::parse_json_value(value, mapping.value_parser, self.config.value_mappings(&mapping.value_mappings))
+                    .ok_or_else(|| SelectorError::new(&format!("Value {} of metric {} can't be parsed as a number", value, mapping.name), None))?;
+                let metric_labels = if labels.is_empty() { None } else { Some(labels) };
+                return Ok(vec![PromMetric::new(mapping.name.to_string(), Some(metric_value), metric_labels)
+                    .with_type(metric_type)
+                    .with_help(mapping.help.clone())])
+            }
+        }.map_err(|message| SelectorError::new(&format!("Metric {}: {}", mapping.name, message), None))?;
+
+        Ok(samples.into_iter()
+            .map(|sample| {
+                let mut sample_labels = labels.clone();
+                sample_labels.extend(sample.label);
+                PromMetric::new(mapping.name.to_string(), Some(sample.value), Some(sample_labels))
+                    .with_type(metric_type)
+                    .with_help(mapping.help.clone())
+                    .with_suffix(sample.suffix)
+            })
+            .collect())
+    }
+
+    fn resolve(&self, selector: &str) -> Result<Vec<Value>, SelectorError> {
+        self.jq.resolve_input(self.json_document, selector)
+            .map_err(|err| SelectorError::new(&format!("Failed to resolve selector {}", selector), Some(err)))
+    }
+}
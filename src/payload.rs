@@ -1,10 +1,15 @@
 use serde_json::Value;
 use convert_case::{Case, Casing};
 use std::collections::HashMap;
-use crate::config_file::ConfigFile;
+use std::time::Instant;
+use crate::config_file::Module;
 use crate::custom_include::processor::CustomIncludeProcessor;
-use crate::jq::Jq;
+use crate::flatten_processor::FlattenProcessor;
+use crate::jq::JqInput;
 use crate::json_object_processor::JsonObjectProcessor;
+use crate::metric_mapping::MetricMappingProcessor;
+use crate::record_processor::RecordProcessor;
+use crate::rule_error::{RuleError, RuleErrors};
 use crate::prom_label::PromLabel;
 use crate::prom_metric::PromMetric;
 use crate::utils;
@@ -13,93 +18,169 @@ use crate::payload_error::PayloadError;
 
 pub struct Payload<'a> {
     full_json_document: String,
-    payload_document: String,
-    config: &'a ConfigFile,
-    jq: Jq
+    json_entry_point: String,
+    config: &'a Module,
+    deadline: Option<Instant>,
+    rule_errors: RuleErrors
 }
 
 impl<'a> Payload<'a> {
-    pub fn new(json: String, json_entry_point: Option<String>, config: &'a ConfigFile) -> Self {
-        let jq = Jq::new().unwrap();
+    pub fn new(json: String, json_entry_point: Option<String>, config: &'a Module) -> Self {
         let default_query = ".".to_string(); // `.` is the jq filter that returns the entire document
-        let payload_document = jq.resolve_raw(&json, &json_entry_point.unwrap_or(default_query)).unwrap(); //TODO: "HANDLE THIS ERROR ACCORDINGLY"
+
+        let json = if config.records.is_some() { json_stream_to_array(json) } else { json };
 
         Self {
-            jq: jq,
             full_json_document: json,
-            config: config,
-            payload_document: payload_document
+            json_entry_point: json_entry_point.unwrap_or(default_query),
+            config,
+            deadline: None,
+            rule_errors: RuleErrors::new(config.is_strict())
         }
     }
 
+    /// Conversion gets aborted with `PayloadError::TimeoutError` once the deadline passed
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Rules that failed and got skipped during `json_to_metrics`
+    pub fn rule_errors(&self) -> Vec<RuleError> {
+        self.rule_errors.errors()
+    }
+
     pub fn json_to_metrics(&self) -> Result<Vec<PromMetric>, PayloadError> {
-        let json_object: HashMap<String, Value> = serde_json::from_str(&self.payload_document)?;
+        let full_document = JqInput::new(&serde_json::from_str(&self.full_json_document)?);
+        let mut entry_point_values = self.config.jq.resolve_input(&full_document, &self.json_entry_point)
+            .map_err(|err| SelectorError::new("Failed to resolve entry point", Some(err)))?;
+        // In record mode, anything but a single array is a list of records, even a single record
+        let payload_value = match (entry_point_values.len(), &self.config.records) {
+            (1, Some(_)) if entry_point_values[0].is_array() => entry_point_values.remove(0),
+            (_, Some(_)) => Value::Array(entry_point_values),
+            (1, None) => entry_point_values.remove(0),
+            (count, None) => return Err(SelectorError::new(
+                &format!("Entry point {} yields {} values, expected 1", self.json_entry_point, count),
+                None
+            ).into())
+        };
         let mut metrics = vec![];
+        self.check_deadline()?;
 
         let global_labels = if self.config.global_labels.is_some() {
-            Some(self.fetch_global_metric_labels()?)
+            Some(self.fetch_global_metric_labels(&full_document)?)
         } else {
             None
         };
+        self.check_deadline()?;
 
-        for root_key in json_object {
-            if root_key.1.is_object() {
-                let processor = JsonObjectProcessor::new(root_key.0, root_key.1, global_labels.clone()).unwrap();
-                if let Some(mut m) = processor.visit(&self.config) {
-                    metrics.append(&mut m);
+        if let (Value::Array(records), Some(records_config)) = (&payload_value, &self.config.records) {
+            let value_mappings = self.config.value_mappings(&records_config.value_mappings);
+            let record_processor = RecordProcessor::new(records_config, value_mappings, &self.config.jq, &global_labels);
+            metrics.append(&mut record_processor.process(records, &self.rule_errors)?);
+        }
+        else if let (true, Some(flatten)) = (self.config.auto_convert(), &self.config.flatten) {
+            let flatten_processor = FlattenProcessor::new(flatten, &self.config.field_parsers, &global_labels);
+            metrics.append(&mut flatten_processor.process(&payload_value));
+        }
+        else if self.config.auto_convert() && payload_value.is_object() {
+            let json_object: HashMap<String, Value> = serde_json::from_value(payload_value.clone())?;
+            for root_key in json_object {
+                self.check_deadline()?;
+                if root_key.1.is_object() {
+                    let rule = format!("object:{}", root_key.0);
+                    let processor = JsonObjectProcessor::new(root_key.0, root_key.1, global_labels.clone()).unwrap();
+                    if let Some(mut m) = self.rule_errors.isolate(&rule, processor.visit(self.config))?.flatten() {
+                        metrics.append(&mut m);
+                    }
                 }
-            }
-            else if root_key.1.is_number() {
-                if let Some(m) = self.visit_number(root_key, &global_labels) {
-                    metrics.push(m);
+                else if root_key.1.is_number() || self.config.field_parser(&root_key.0).is_some() {
+                    if let Some(m) = self.visit_value(root_key, &global_labels) {
+                        metrics.push(m);
+                    }
                 }
             }
         }
 
-        if self.config.includes.is_some() {
+        // Converted once, every include and metrics selector runs against it
+        let payload_input = (self.config.includes.is_some() || self.config.metrics.is_some())
+            .then(|| JqInput::new(&payload_value));
+
+        if let (Some(_), Some(payload_input)) = (&self.config.includes, &payload_input) {
             let include_processor = CustomIncludeProcessor::new(
-                self.payload_document.to_string(),
-                self.config.clone(),
-                    global_labels.clone(),
-                    self.jq.clone()
+                payload_input,
+                self.config,
+                &global_labels,
+                &self.config.jq
+            ).with_deadline(self.deadline);
+            metrics.append(&mut include_processor.process(&self.rule_errors)?);
+        }
+
+        if let (Some(_), Some(payload_input)) = (&self.config.metrics, &payload_input) {
+            self.check_deadline()?;
+            let mapping_processor = MetricMappingProcessor::new(
+                self.config,
+                &self.config.jq,
+                payload_input,
+                &global_labels
             );
-            metrics.append(&mut include_processor.process()?);
+            metrics.append(&mut mapping_processor.process(&self.rule_errors)?);
         }
 
         Ok(metrics)
     }
 
-    fn fetch_global_metric_labels(&self) -> Result<Vec<PromLabel>, SelectorError> {
+    fn check_deadline(&self) -> Result<(), PayloadError> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(PayloadError::TimeoutError),
+            _ => Ok(())
+        }
+    }
+
+    /// Every global label is a rule of its own, labels that fail are left out
+    fn fetch_global_metric_labels(&self, full_document: &JqInput) -> Result<Vec<PromLabel>, PayloadError> {
         let mut labels = vec!();
         for global_label in self.config.global_labels.as_ref().unwrap() {
-            let raw_value = self.jq.resolve_json_scalar_value(
-                &self.full_json_document,
+            let raw_value = self.config.jq.resolve_json_scalar_value(
+                full_document,
                 &global_label.selector
-            );
+            ).map_err(|err| SelectorError::new("Failed to fetch global metric", Some(err)));
 
-            match raw_value {
-                Ok(val) => labels.push(PromLabel::new(global_label.name.to_string(), val.trim().to_string())),
-                Err(err) => return Err(SelectorError::new("Failed to fetch global metric", Some(err)))
+            let rule = format!("global_label:{}", global_label.name);
+            if let Some(val) = self.rule_errors.isolate(&rule, raw_value)? {
+                labels.push(PromLabel::new(global_label.name.to_string(), val.trim().to_string()));
             }
         }
         Ok(labels)
     }
 
-    fn visit_number(&self, json_value: (String, Value), global_labels: &Option<Vec<PromLabel>>) -> Option<PromMetric> {
+    /// Numbers, and strings of fields with a value parser
+    fn visit_value(&self, json_value: (String, Value), global_labels: &Option<Vec<PromLabel>>) -> Option<PromMetric> {
         let metric_name = json_value.0.to_case(Case::Snake);
-        if let Some(num) = utils::json_number_to_i64(&json_value.1) {
-            Some(PromMetric::new(metric_name, Some(num), global_labels.clone()))
-        } else {
-            None
-        }
+        utils::parse_json_value(&json_value.1, self.config.field_parser(&json_value.0), None)
+            .map(|num| PromMetric::new(metric_name, Some(num), global_labels.clone()))
     }
 }
 
+/// In record mode, newline delimited JSON holds several records, they get wrapped into an array.
+/// Single documents, and invalid ones, are returned unchanged
+fn json_stream_to_array(json: String) -> String {
+    let documents = serde_json::Deserializer::from_str(&json)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<_>, _>>();
+
+    match documents {
+        Ok(documents) if documents.len() > 1 => Value::Array(documents).to_string(),
+        _ => json
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use std::error::Error;
-    use crate::{config_file::{self, ConfigFile}, payload::Payload, prom_metric::PromMetric};
+    use std::time::{Duration, Instant};
+    use crate::{config_file::Module, payload::Payload, prom_metric::{MetricType, PromMetric}};
+    use crate::rule_error::RuleError;
     use super::PayloadError;
     use assert_matches::assert_matches;
 
@@ -207,7 +288,54 @@ mod tests {
         }"#.to_string()
     }
 
-    fn config_without_gauge_mapping() -> ConfigFile {
+    fn json_with_backend_list() -> String {
+        r#"{
+            "environment": "production",
+            "router": {
+                "backends": [
+                    { "name": "eu-1", "status": 1 },
+                    { "name": "eu-2", "status": 0 },
+                    { "name": "us-1", "status": 1 }
+                ]
+            }
+        }"#.to_string()
+    }
+
+    fn json_with_float_values() -> String {
+        r#"{
+            "cpu_load": 0.75,
+            "bytes_total": 18446744073709551615,
+            "components": {
+                "disk": {
+                    "status": 0.5,
+                    "mount": "/var"
+                }
+            }
+        }"#.to_string()
+    }
+
+    fn json_with_records() -> String {
+        r#"[
+            {"name": "queue_depth", "value": 3, "host": "mq-1"},
+            {"name": "queue_depth", "value": 4, "host": "mq-2"},
+            {"name": "queue_consumers", "value": 2, "host": "mq-1"},
+            {"name": "queue_state", "value": "draining", "host": "mq-2"}
+        ]"#.to_string()
+    }
+
+    fn ndjson_with_records() -> String {
+        [
+            r#"{"metric": "requests", "count": 120, "route": "/api"}"#,
+            r#"{"metric": "requests", "count": 7, "route": "/health"}"#
+        ].join("\n")
+    }
+
+    fn config_with_records(records: &str) -> Module {
+        let yaml_str = format!("global_labels:\n  - name: source\n    selector: .[0].host\nrecords:\n{}", records);
+        Module::from_yaml_for_test(&yaml_str)
+    }
+
+    fn config_without_gauge_mapping() -> Module {
         let yaml_str = r#"
 gauge_field: status
 global_labels:
@@ -216,10 +344,17 @@ global_labels:
     - name: id
       selector: .id
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_without_gauge_mapping_or_labels() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+"#;
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_non_existing_global_labels() -> ConfigFile {
+    fn config_with_non_existing_global_labels() -> Module {
         let yaml_str = r#"
 gauge_field: status
 global_labels:
@@ -228,10 +363,10 @@ global_labels:
     - name: id
       selector: .id
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_gauge_mapping() -> ConfigFile {
+    fn config_with_gauge_mapping() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -243,10 +378,10 @@ global_labels:
     - name: id
       selector: .id
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_custom_include_and_no_gauge_field_values() -> ConfigFile {
+    fn config_with_custom_include_and_no_gauge_field_values() -> Module {
         let yaml_str = r#"
 gauge_field: status
 global_labels:
@@ -262,10 +397,10 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_custom_includes() -> ConfigFile {
+    fn config_with_custom_includes() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -284,10 +419,10 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
     }
 
-    fn config_with_custom_includes_and_invalid_label_selector() -> ConfigFile {
+    fn config_with_custom_includes_and_invalid_label_selector() -> Module {
         let yaml_str = r#"
 gauge_field: status
 gauge_field_values:
@@ -306,7 +441,128 @@ includes:
         - ".router.backend.back1"
         - ".router.backend.back2"
 "#;
-        config_file::ConfigFile::from_str(yaml_str).unwrap()
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_metric_mappings(auto_convert: bool) -> Module {
+        let yaml_str = format!(r#"
+auto_convert: {}
+global_labels:
+    - name: environment
+      selector: .environment
+metrics:
+    - name: router_uplinks
+      value: .components.router.num_uplinks
+      type: gauge
+      help: Number of router uplinks
+      labels:
+        router_status: .components.router.status
+    - name: backend_healthy
+      value: .components.router.backend[].healthy_count
+      labels:
+        backend: .components.router.backend | keys_unsorted[]
+"#, auto_convert);
+        Module::from_yaml_for_test(&yaml_str)
+    }
+
+    fn config_with_mismatching_metric_mapping_labels() -> Module {
+        let yaml_str = r#"
+metrics:
+    - name: backend_healthy
+      value: .components.router.backend[].healthy_count
+      labels:
+        backend: .components.router.backend | keys_unsorted[] | ., .
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_value_mappings() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+value_mappings:
+    values:
+        ok: 2
+        warning: 1
+    fallback: 0
+metrics:
+    - name: router_state
+      value: .router.status
+      value_mappings:
+        patterns:
+          - regex: "^Warn"
+            value: 5
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn json_with_encoded_values() -> String {
+        r#"{
+            "uptime": "3d4h12m",
+            "last_seen": "2026-10-17T12:00:00Z",
+            "components": {
+                "jvm": {
+                    "status": "73%",
+                    "heap": "512MiB"
+                }
+            }
+        }"#.to_string()
+    }
+
+    fn config_with_value_parsers() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+field_parsers:
+    uptime: duration
+    last_seen: timestamp
+    status: percent
+metrics:
+    - name: jvm_heap_bytes
+      value: .components.jvm.heap
+      value_parser: bytes
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_info_metrics() -> Module {
+        let yaml_str = r#"
+gauge_field: status
+global_labels:
+    - name: environment
+      selector: .environment
+info_metrics: true
+field_modes:
+    use_ip_v6: label
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_flatten() -> Module {
+        let yaml_str = r#"
+flatten:
+  label_keys:
+    backend: backend
+"#;
+        Module::from_yaml_for_test(yaml_str)
+    }
+
+    fn config_with_iterated_include(selector: &str, label_value: &str, iterate: bool) -> Module {
+        let yaml_str = format!(r#"
+gauge_field: status
+auto_convert: false
+includes:
+    - name: router_backend_status
+      label_name: backend
+      selector: "{}"
+      label_value: {}
+      iterate: {}
+"#, selector, label_value, iterate);
+        Module::from_yaml_for_test(&yaml_str)
+    }
+
+    fn backend_labels(metrics: &[PromMetric]) -> Vec<String> {
+        metrics.iter()
+            .map(|m| m.labels.as_ref().unwrap().iter().find(|l| l.name == "backend").unwrap().value.to_string())
+            .collect()
     }
 
     fn create_metrics() -> Vec<PromMetric> {
@@ -327,7 +583,7 @@ includes:
                                         .map(|metric| metric.name.to_string())
                                         .collect::<Vec<_>>();
 
-        payload_names.sort_by(|a, b| a.cmp(&b));
+        payload_names.sort();
 
         assert_eq!(payload_names, vec![
             "api_http_requests_total",
@@ -336,23 +592,66 @@ includes:
         ]);
     }
 
+    #[test]
+    fn convert_json_object_with_float_values() {
+        let json_str = json_with_float_values();
+        let config = config_without_gauge_mapping_or_labels();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        let cpu_load = metrics.iter().find(|m| m.name == "cpu_load").unwrap();
+        assert_eq!(cpu_load.value, Some(0.75));
+        let bytes_total = metrics.iter().find(|m| m.name == "bytes_total").unwrap();
+        assert_eq!(bytes_total.value, Some(u64::MAX as f64));
+    }
+
+    #[test]
+    fn convert_json_object_with_float_gauge_field() {
+        let json_str = json_with_float_values();
+        let config = config_without_gauge_mapping_or_labels();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics[0].name, "disk_status");
+        assert_eq!(metrics[0].value, Some(0.5));
+    }
+
     #[test]
     fn convert_json_object_invalid_global_label_selector() {
         //We want to test what happens when we try to fetch global labels from the json
         //that do not exist
         let json_str = json_with_numeric_values();
-        let config = config_with_non_existing_global_labels();
+        let mut config = config_with_non_existing_global_labels();
+        config.strict = Some(true);
         let payload = Payload::new(json_str, None, &config);
         match payload.json_to_metrics().unwrap_err() {
             PayloadError::SelectorError(err) => {
                 assert!(err.source().is_some());
             },
-            _ => {
-                assert!(false);
+            err => {
+                panic!("Expected SelectorError, got {:?}", err);
             }
         }
     }
 
+    #[test]
+    fn convert_json_object_invalid_global_label_selector_skips_label() {
+        let json_str = json_with_numeric_values();
+        let config = config_with_non_existing_global_labels();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 3);
+        assert!(metrics.iter().all(|m| {
+            let labels = m.labels.as_ref().unwrap();
+            labels.len() == 1 && labels[0].name == "id"
+        }));
+        assert_eq!(payload.rule_errors(), vec![RuleError {
+            rule: "global_label:Does not exist".to_string(),
+            reason: "non_scalar_value"
+        }]);
+    }
+
     #[test]
     fn convert_json_object_no_entry_point_does_not_convert_child_object() {
         let json_str = json_with_numeric_values();
@@ -368,21 +667,21 @@ includes:
     fn convert_json_object_with_correct_status_field_config() {
         let metrics = create_metrics();
         assert_eq!(metrics[0].name, "network_status");
-        assert_eq!(metrics[0].value, Some(1));
+        assert_eq!(metrics[0].value, Some(1.0));
     }
 
     #[test]
     fn convert_full_json_file_extract_global_labels() {
         let metrics = create_metrics();
         assert_eq!(metrics[0].name, "network_status");
-        assert_eq!(metrics[0].value, Some(1));
+        assert_eq!(metrics[0].value, Some(1.0));
 
         let labels = metrics[0].labels.as_ref().unwrap();
 
-        let l1 = labels.into_iter().find(|l| l.name == "environment").unwrap();
+        let l1 = labels.iter().find(|l| l.name == "environment").unwrap();
         assert_eq!(l1.value, "production");
 
-        let l2 = labels.into_iter().find(|l| l.name == "id").unwrap();
+        let l2 = labels.iter().find(|l| l.name == "id").unwrap();
         assert_eq!(l2.value, "xyz");
     }
 
@@ -396,7 +695,7 @@ includes:
                 .map(|label| label.name.to_string())
                 .collect::<Vec<String>>();
 
-        label_names.sort_by(|a, b| a.cmp(b));
+        label_names.sort();
 
         assert_eq!(label_names, vec![
             "environment",
@@ -439,7 +738,7 @@ includes:
         let payload = Payload::new(json_str, Some(".".into()), &config);
         let metrics = payload.json_to_metrics().unwrap();
         assert_eq!(metrics[0].name, "last_refresh_epoch");
-        assert_eq!(metrics[0].value, Some(1631046901));
+        assert_eq!(metrics[0].value, Some(1631046901.0));
     }
 
     #[test]
@@ -491,8 +790,8 @@ includes:
         let router_metrics = metrics.iter().filter(|m| m.name == "router_status").collect::<Vec<_>>();
         assert_eq!(router_metrics.len(), 2);
 
-        let has_one_flag = router_metrics.iter().any(|m| m.value == Some(1));
-        let has_zero_flag = router_metrics.iter().any(|m| m.value == Some(0));
+        let has_one_flag = router_metrics.iter().any(|m| m.value == Some(1.0));
+        let has_zero_flag = router_metrics.iter().any(|m| m.value == Some(0.0));
 
         assert!(has_one_flag);
         assert!(has_zero_flag);
@@ -545,14 +844,91 @@ includes:
         assert!(has_include_label);
     }
 
+    #[test]
+    fn convert_json_custom_include_with_state_set() {
+        let json_str = json_with_several_components();
+        let mut config = config_with_custom_include_and_no_gauge_field_values();
+        config.state_sets = Some(serde_yaml::from_str("status:\n  states: [ok, warning]\n  discover: true\n").unwrap());
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+        let states = metrics.iter()
+            .filter(|m| m.name == "router_backend_status")
+            .map(|m| (m.labels.as_ref().unwrap().last().unwrap().value.to_string(), m.value.unwrap()))
+            .collect::<Vec<_>>();
+
+        assert!(metrics.iter().filter(|m| m.name == "router_backend_status").all(|m| m.metric_type == MetricType::StateSet));
+        assert_eq!(states, vec![
+            ("ok".to_string(), 0.0), ("warning".to_string(), 1.0),
+            ("ok".to_string(), 0.0), ("warning".to_string(), 1.0)
+        ]);
+    }
+
     #[test]
     fn convert_json_custom_include_with_invalid_selector_returns_error() {
         let json_str = json_with_several_components();
-        let config = config_with_custom_includes_and_invalid_label_selector();
+        let mut config = config_with_custom_includes_and_invalid_label_selector();
+        config.strict = Some(true);
         let payload = Payload::new(json_str, Some(".components".into()), &config);
         let metrics_or_error= payload.json_to_metrics();
         assert!(metrics_or_error.is_err());
-        assert_matches!(metrics_or_error.unwrap_err(), PayloadError::SelectorError(_));
+        assert_matches!(metrics_or_error.unwrap_err(), PayloadError::IncludeError(_));
+    }
+
+    #[test]
+    fn convert_json_custom_include_with_invalid_selector_skips_include() {
+        let json_str = json_with_several_components();
+        let config = config_with_custom_includes_and_invalid_label_selector();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert!(metrics.iter().any(|m| m.name == "router_status"));
+        assert!(!metrics.iter().any(|m| m.name == "router_backend_status"));
+        assert_eq!(payload.rule_errors(), vec![RuleError {
+            rule: "include:router_backend_status".to_string(),
+            reason: "unexpected_value"
+        }]);
+    }
+
+    #[test]
+    fn convert_json_with_null_status_skips_rule() {
+        let json_str = r#"{
+            "network": {"status": "ok"},
+            "storage": {"status": null},
+            "router": {
+                "status": "warning",
+                "backend": {
+                    "back1": {"status": null},
+                    "back2": {"status": "ok"}
+                }
+            }
+        }"#.to_string();
+        let yaml_str = r#"
+gauge_field: status
+gauge_field_values: [ok, warning]
+includes:
+    - name: router_backend_status
+      label_name: backend
+      selector: ".router.backend"
+      label_value: key
+      iterate: true
+    - name: network_link_status
+      label_name: link
+      selector: ".network"
+      label_value: index
+"#;
+        let config = Module::from_yaml_for_test(yaml_str);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.iter().filter(|m| m.name == "network_link_status").count(), 2);
+        assert_eq!(metrics.iter().filter(|m| m.name == "router_status").count(), 2);
+        assert!(!metrics.iter().any(|m| m.name == "router_backend_status" || m.name == "storage_status"));
+        let mut rule_errors = payload.rule_errors();
+        rule_errors.sort_by(|error, other| error.rule.cmp(&other.rule));
+        assert_eq!(rule_errors, vec![
+            RuleError { rule: "include:router_backend_status".to_string(), reason: "non_scalar_value" },
+            RuleError { rule: "object:storage".to_string(), reason: "non_scalar_value" }
+        ]);
     }
 
     #[test]
@@ -588,6 +964,294 @@ includes:
         assert!(metrics[1].labels.as_ref().unwrap().iter().find(|l| l.name == "status").is_none());
     }
 
+    #[test]
+    fn convert_json_metric_mapping_resolves_value_and_labels() {
+        let json_str = json_with_several_components();
+        let config = config_with_metric_mappings(false);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        let uplinks = metrics.iter().find(|m| m.name == "router_uplinks").unwrap();
+        assert_eq!(uplinks.value, Some(2.0));
+        assert_eq!(uplinks.metric_type, MetricType::Gauge);
+        assert_eq!(uplinks.help.as_deref(), Some("Number of router uplinks"));
+
+        let labels = uplinks.labels.as_ref().unwrap();
+        assert_eq!(labels.iter().find(|l| l.name == "environment").unwrap().value, "production");
+        assert_eq!(labels.iter().find(|l| l.name == "router_status").unwrap().value, "Warning");
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_one_sample_per_value() {
+        let json_str = json_with_several_components();
+        let config = config_with_metric_mappings(false);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        let backends = metrics.iter()
+            .filter(|m| m.name == "backend_healthy")
+            .map(|m| m.labels.as_ref().unwrap().iter().find(|l| l.name == "backend").unwrap().value.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(backends, vec!["back1", "back2"]);
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_without_auto_convert() {
+        let json_str = json_with_several_components();
+        let config = config_with_metric_mappings(false);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 3);
+        assert!(metrics.iter().all(|m| m.name == "router_uplinks" || m.name == "backend_healthy"));
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_with_auto_convert() {
+        let json_str = json_with_several_components();
+        let config = config_with_metric_mappings(true);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert!(metrics.iter().any(|m| m.name == "last_refresh_epoch"));
+        assert!(metrics.iter().any(|m| m.name == "router_uplinks"));
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_with_mismatching_labels_returns_error() {
+        let json_str = json_with_several_components();
+        let mut config = config_with_mismatching_metric_mapping_labels();
+        config.strict = Some(true);
+        let payload = Payload::new(json_str, None, &config);
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::SelectorError(_)));
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_with_mismatching_labels_keeps_other_metrics() {
+        let json_str = json_with_several_components();
+        let config = config_with_mismatching_metric_mapping_labels();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert!(metrics.iter().any(|m| m.name == "last_refresh_epoch"));
+        assert!(!metrics.iter().any(|m| m.name == "backend_healthy"));
+        assert_eq!(payload.rule_errors(), vec![RuleError {
+            rule: "metric:backend_healthy".to_string(),
+            reason: "unexpected_value"
+        }]);
+    }
+
+    fn config_with_invalid_metric_mapping_values() -> Module {
+        Module::from_yaml_for_test(r#"
+auto_convert: false
+metrics:
+    - name: router
+      value: .router
+    - name: router_state
+      value: .router.state
+    - name: router_uplinks
+      value: .router.uplinks
+      labels:
+        backend: .router.backend
+    - name: router_load
+      value: .router.load
+"#)
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_with_invalid_values_reports_rule_errors() {
+        let json_str = r#"{"router": {"state": "degraded", "uplinks": 2, "load": 0.5, "backend": {"name": "back1"}}}"#.to_string();
+        let config = config_with_invalid_metric_mapping_values();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["router_load"]);
+        assert_eq!(payload.rule_errors(), vec![
+            RuleError { rule: "metric:router".to_string(), reason: "non_scalar_value" },
+            RuleError { rule: "metric:router_state".to_string(), reason: "unexpected_value" },
+            RuleError { rule: "metric:router_uplinks".to_string(), reason: "non_scalar_value" }
+        ]);
+    }
+
+    #[test]
+    fn convert_json_metric_mapping_with_invalid_values_in_strict_mode_returns_error() {
+        let json_str = r#"{"router": {"state": "degraded", "uplinks": 2, "load": 0.5, "backend": {"name": "back1"}}}"#.to_string();
+        let mut config = config_with_invalid_metric_mapping_values();
+        config.strict = Some(true);
+        let payload = Payload::new(json_str, None, &config);
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::SelectorError(_)));
+    }
+
+    #[test]
+    fn convert_json_iterated_include_labels_by_object_key() {
+        let json_str = json_with_numerical_status();
+        let config = config_with_iterated_include(".router.backend", "key", true);
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["back1", "back2"]);
+        assert!(metrics.iter().all(|m| m.value == Some(2.0)));
+    }
+
+    #[test]
+    fn convert_json_iterated_include_labels_by_array_index() {
+        let json_str = json_with_backend_list();
+        let config = config_with_iterated_include(".router.backends[]", "index", false);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["0", "1", "2"]);
+        assert_eq!(metrics.iter().map(|m| m.value.unwrap()).collect::<Vec<_>>(), vec![1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn convert_json_iterated_include_labels_by_element_selector() {
+        let json_str = json_with_backend_list();
+        let config = config_with_iterated_include(".router.backends[] | select(.name | startswith(\\\"eu\\\"))", ".name", false);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["eu-1", "eu-2"]);
+    }
+
+    #[test]
+    fn convert_json_iterated_include_with_to_entries() {
+        let json_str = r#"{"backends": {"eu-1": 1, "eu-2": 0, "us-1": 1}}"#.to_string();
+        let mut config = config_with_iterated_include(".backends | to_entries[] | select(.key | startswith(\\\"eu\\\"))", ".key", false);
+        config.gauge_field = "value".to_string();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["eu-1", "eu-2"]);
+        assert_eq!(metrics.iter().map(|m| m.value.unwrap()).collect::<Vec<_>>(), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn convert_json_iterated_include_labels_by_array_index_when_iterating() {
+        let json_str = json_with_backend_list();
+        let config = config_with_iterated_include(".router.backends", "index", true);
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["0", "1", "2"]);
+    }
+
+    #[test]
+    fn convert_json_iterated_include_keeps_key_value_shaped_elements() {
+        let json_str = r#"{"pairs": [{"key": "eu-1", "value": 1}, {"key": "eu-2", "value": 0}]}"#.to_string();
+        let mut config = config_with_iterated_include(".pairs[]", ".key", false);
+        config.gauge_field = "value".to_string();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(backend_labels(&metrics), vec!["eu-1", "eu-2"]);
+        assert_eq!(metrics.iter().map(|m| m.value.unwrap()).collect::<Vec<_>>(), vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn convert_json_iterated_include_key_of_array_element_returns_error() {
+        let json_str = json_with_backend_list();
+        let mut config = config_with_iterated_include(".router.backends", "key", true);
+        config.strict = Some(true);
+        let payload = Payload::new(json_str, None, &config);
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::IncludeError(_)));
+    }
+
+    #[test]
+    fn convert_json_status_field_with_value_mappings() {
+        let json_str = json_with_several_components();
+        let config = config_with_value_mappings();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+        let value_of = |name: &str| metrics.iter().find(|m| m.name == name).unwrap().value;
+
+        assert_eq!(value_of("network_status"), Some(2.0));
+        assert_eq!(value_of("router_status"), Some(1.0));
+        assert_eq!(value_of("router_state"), Some(5.0));
+    }
+
+    #[test]
+    fn convert_json_with_value_parsers() {
+        let json_str = json_with_encoded_values();
+        let config = config_with_value_parsers();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+        let value_of = |name: &str| metrics.iter().find(|m| m.name == name).unwrap().value;
+
+        assert_eq!(value_of("uptime"), Some(274320.0));
+        assert_eq!(value_of("last_seen"), Some(1792238400.0));
+        assert_eq!(value_of("jvm_heap_bytes"), Some(536870912.0));
+        assert_eq!(metrics.len(), 3);
+    }
+
+    #[test]
+    fn convert_json_gauge_field_with_value_parser() {
+        let json_str = json_with_encoded_values();
+        let config = config_with_value_parsers();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.iter().find(|m| m.name == "jvm_status").unwrap().value, Some(0.73));
+    }
+
+    #[test]
+    fn convert_json_with_info_metrics_reports_numeric_fields_as_metrics() {
+        let json_str = json_with_several_components();
+        let config = config_with_info_metrics();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+        let value_of = |name: &str| metrics.iter().find(|m| m.name == name).unwrap().value;
+        let num_uplinks = metrics.iter().find(|m| m.name == "router_num_uplinks").unwrap();
+        let router_labels = num_uplinks.labels.as_ref().unwrap().iter().map(|l| l.name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(value_of("router_num_uplinks"), Some(2.0));
+        assert_eq!(value_of("router_num_active_uplinks"), Some(1.0));
+        assert_eq!(value_of("network_has_ip_addresses"), Some(1.0));
+        assert_eq!(router_labels, vec!["environment"]);
+    }
+
+    #[test]
+    fn convert_json_with_info_metrics_puts_strings_into_info_metric() {
+        let json_str = json_with_several_components();
+        let config = config_with_info_metrics();
+        let payload = Payload::new(json_str, Some(".components".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+        let network_info = metrics.iter().find(|m| m.name == "network_info").unwrap();
+        let network_labels = |name: &str| metrics.iter().find(|m| m.name == name).unwrap().labels.as_ref().unwrap().iter()
+            .map(|l| format!("{}={}", l.name, l.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(network_info.metric_type, MetricType::Info);
+        assert_eq!(network_info.value, Some(1.0));
+        assert_eq!(network_labels("network_info"), vec!["environment=production", "use_ip_v6=false", "status_upstream=active"]);
+        assert_eq!(network_labels("network_status"), vec!["environment=production", "use_ip_v6=false"]);
+        assert!(metrics.iter().all(|m| m.name != "router_info" && m.name != "network_use_ip_v6"));
+    }
+
+    #[test]
+    fn convert_json_with_flatten_walks_whole_tree() {
+        let json_str = json_with_several_components();
+        let config = config_with_flatten();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        let healthy_counts = metrics.into_iter()
+            .filter(|m| m.name == "components_router_backend_healthy_count")
+            .collect::<Vec<_>>();
+        assert_eq!(backend_labels(&healthy_counts), vec!["back1", "back2"]);
+    }
+
+    #[test]
+    fn convert_json_with_flatten_converts_root_and_boolean_values() {
+        let json_str = json_with_several_components();
+        let config = config_with_flatten();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert!(metrics.iter().any(|m| m.name == "last_refresh_epoch"));
+        assert!(metrics.iter().any(|m| m.name == "components_network_has_ip_addresses" && m.value == Some(1.0)));
+    }
+
     #[test]
     fn convert_json_with_custom_include_no_duplicate_status_tags() {
         let json_str = json_with_several_components();
@@ -599,4 +1263,116 @@ includes:
             assert_eq!(metric.labels.as_ref().unwrap().iter().filter(|l| l.name == "status").count(), 1, "metric {} has more than one status label", metric.name);
         }
     }
+
+    #[test]
+    fn convert_json_with_expired_deadline_returns_timeout_error() {
+        let json_str = json_with_several_components();
+        let config = config_with_custom_includes();
+        let payload = Payload::new(json_str, Some(".components".into()), &config)
+            .with_deadline(Instant::now());
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::TimeoutError));
+    }
+
+    #[test]
+    fn convert_json_within_deadline() {
+        let json_str = json_with_several_components();
+        let config = config_with_custom_includes();
+        let payload = Payload::new(json_str, Some(".components".into()), &config)
+            .with_deadline(Instant::now() + Duration::from_secs(60));
+        assert!(payload.json_to_metrics().is_ok());
+    }
+
+    #[test]
+    fn convert_json_array_of_records() {
+        let config = config_with_records("  labels:\n    host: .host\n");
+        let payload = Payload::new(json_with_records(), None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(metrics[1].name, "queue_depth");
+        assert_eq!(metrics[1].value, Some(4.0));
+        let labels = metrics[1].labels.as_ref().unwrap().iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["source=\"mq-1\"", "host=\"mq-2\""]);
+    }
+
+    #[test]
+    fn convert_ndjson_records_with_custom_fields() {
+        let config = config_with_records("  name: .metric\n  value: .count\n  type: counter\n  labels:\n    route: .route\n");
+        let ndjson = ndjson_with_records().replace(r#""route""#, r#""host": "web-1", "route""#);
+        let payload = Payload::new(ndjson, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 2);
+        assert!(metrics.iter().all(|m| m.name == "requests" && m.metric_type == MetricType::Counter));
+        assert_eq!(metrics[1].value, Some(7.0));
+    }
+
+    #[test]
+    fn convert_records_yielded_by_entry_point() {
+        let json_str = format!(r#"{{"items": {}}}"#, json_with_records());
+        let config = Module::from_yaml_for_test("records: {}");
+        let payload = Payload::new(json_str, Some(".items[] | select(.host == \"mq-1\")".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["queue_depth", "queue_consumers"]);
+    }
+
+    #[test]
+    fn convert_single_record_yielded_by_entry_point() {
+        let json_str = format!(r#"{{"items": {}}}"#, json_with_records());
+        let config = Module::from_yaml_for_test("records: {}");
+        let payload = Payload::new(json_str, Some(".items[] | select(.name == \"queue_consumers\")".into()), &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "queue_consumers");
+        assert_eq!(metrics[0].value, Some(2.0));
+    }
+
+    #[test]
+    fn convert_single_line_ndjson_records() {
+        let config = Module::from_yaml_for_test("records:\n  name: .metric\n  value: .count\n");
+        let payload = Payload::new(r#"{"metric": "requests", "count": 120, "route": "/api"}"#.to_string(), None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].name, "requests");
+        assert_eq!(metrics[0].value, Some(120.0));
+    }
+
+    #[test]
+    fn convert_multi_value_entry_point_without_records_config_returns_error() {
+        let json_str = format!(r#"{{"items": {}}}"#, json_with_records());
+        let config = config_without_gauge_mapping_or_labels();
+        let payload = Payload::new(json_str, Some(".items[]".into()), &config);
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::SelectorError(_)));
+    }
+
+    #[test]
+    fn convert_ndjson_without_records_config_returns_error() {
+        let config = config_without_gauge_mapping_or_labels();
+        let payload = Payload::new(ndjson_with_records(), None, &config);
+        assert_matches!(payload.json_to_metrics(), Err(PayloadError::JsonError(_)));
+    }
+
+    #[test]
+    fn convert_histogram_with_decreasing_buckets_skips_metric() {
+        let config = Module::from_yaml_for_test("auto_convert: false\nmetrics:\n  - name: latency\n    type: histogram\n    value: .latency\n  - name: requests\n    value: .requests\n");
+        let json_str = r#"{"latency": {"le_0.1": 30, "le_0.5": 12, "count": 40}, "requests": 3}"#.to_string();
+        let payload = Payload::new(json_str, None, &config);
+        let metrics = payload.json_to_metrics().unwrap();
+
+        assert_eq!(metrics.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["requests"]);
+        assert_eq!(payload.rule_errors(), vec![RuleError {
+            rule: "metric:latency".to_string(),
+            reason: "unexpected_value"
+        }]);
+    }
+
+    #[test]
+    fn convert_json_array_without_records_config() {
+        let config = config_without_gauge_mapping_or_labels();
+        let payload = Payload::new(json_with_records(), None, &config);
+        assert!(payload.json_to_metrics().unwrap().is_empty());
+    }
 }
@@ -2,10 +2,40 @@ use crate::{selector_error::SelectorError};
 use crate::custom_include::error::CustomIncludeError;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum PayloadError {
-    IOError(std::io::Error),
     JsonError(serde_json::Error),
-    SelectorError(SelectorError)
+    SelectorError(SelectorError),
+    IncludeError(SelectorError),
+    MissingFieldError(String),
+    NonScalarValueError(String),
+    TimeoutError
+}
+
+impl PayloadError {
+    /// Why a rule failed, reported by `json_exporter_rule_errors_total`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PayloadError::JsonError(_) => "invalid_json",
+            PayloadError::SelectorError(e) | PayloadError::IncludeError(e) => e.reason(),
+            PayloadError::MissingFieldError(_) => "missing_field",
+            PayloadError::NonScalarValueError(_) => "non_scalar_value",
+            PayloadError::TimeoutError => "timeout"
+        }
+    }
+}
+
+impl std::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PayloadError::JsonError(e) => write!(f, "{}", e),
+            PayloadError::SelectorError(e) => write!(f, "{}", e),
+            PayloadError::IncludeError(e) => write!(f, "{}", e),
+            PayloadError::MissingFieldError(e) => write!(f, "{}", e),
+            PayloadError::NonScalarValueError(e) => write!(f, "{}", e),
+            PayloadError::TimeoutError => write!(f, "Deadline exceeded while converting the payload"),
+        }
+    }
 }
 
 impl From<serde_json::Error> for PayloadError {
@@ -23,9 +53,10 @@ impl From<SelectorError> for PayloadError {
 impl From<CustomIncludeError> for PayloadError {
     fn from(err: CustomIncludeError) -> Self {
         match err {
-            CustomIncludeError::IOError(e) => PayloadError::IOError(e),
-            CustomIncludeError::JsonError(e) => PayloadError::JsonError(e),
-            CustomIncludeError::SelectorError(e) => PayloadError::SelectorError(SelectorError::new(&e, None)),
+            CustomIncludeError::JqError(e) => PayloadError::IncludeError(SelectorError::new("Failed to resolve custom include", Some(e))),
+            CustomIncludeError::SelectorError(e) => PayloadError::IncludeError(SelectorError::new(&e, None)),
+            CustomIncludeError::MissingFieldError(e) => PayloadError::MissingFieldError(e),
+            CustomIncludeError::NonScalarValueError(e) => PayloadError::NonScalarValueError(e),
         }
     }
 }
\ No newline at end of file
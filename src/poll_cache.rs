@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::exposition::MetricFamily;
+use crate::prom_metric::MetricType;
+use crate::scrape_result::{ScrapeResult, ScrapeStats};
+
+/// Last successful conversion per module, filled by the background poller
+pub struct PollCache {
+    entries: RwLock<HashMap<String, CacheEntry>>,
+    max_age: Duration
+}
+
+#[derive(Default)]
+struct CacheEntry {
+    last_success: Option<(Vec<MetricFamily>, SystemTime)>,
+    /// Stats of the latest poll, successful or not
+    stats: ScrapeStats
+}
+
+impl PollCache {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            max_age
+        }
+    }
+
+    /// Failed polls keep the previous result until it gets stale
+    pub fn update(&self, module_name: &str, metrics: Option<Vec<MetricFamily>>, stats: ScrapeStats) {
+        let mut entries = self.entries.write().unwrap();
+        let entry = entries.entry(module_name.to_string()).or_default();
+        if let Some(metrics) = metrics {
+            entry.last_success = Some((metrics, SystemTime::now()));
+        }
+        entry.stats = stats;
+    }
+
+    /// Drops the results of modules removed by a config reload
+    pub fn retain_modules(&self, module_names: &[&String]) {
+        self.entries.write().unwrap().retain(|name, _| module_names.contains(&name));
+    }
+
+    pub fn get(&self, module_name: &str) -> CachedScrape {
+        let entries = self.entries.read().unwrap();
+        let entry = match entries.get(module_name) {
+            Some(entry) => entry,
+            None => return CachedScrape { metrics: None, age: None, last_success: None, stats: None }
+        };
+        let (metrics, updated) = match &entry.last_success {
+            Some(last_success) => last_success,
+            None => return CachedScrape { metrics: None, age: None, last_success: None, stats: Some(entry.stats.clone()) }
+        };
+
+        let age = SystemTime::now().duration_since(*updated).unwrap_or_default();
+        let last_success = updated.duration_since(UNIX_EPOCH).map_or(0.0, |duration| duration.as_secs_f64());
+        CachedScrape {
+            metrics: Some(metrics.clone()).filter(|_| age <= self.max_age),
+            age: Some(age),
+            last_success: Some(last_success),
+            stats: Some(entry.stats.clone())
+        }
+    }
+}
+
+/// A cached result as served by `/metrics`. Stale results are reported as failed
+pub struct CachedScrape {
+    metrics: Option<Vec<MetricFamily>>,
+    age: Option<Duration>,
+    last_success: Option<f64>,
+    stats: Option<ScrapeStats>
+}
+
+impl CachedScrape {
+    pub fn into_metric_families(self) -> Vec<MetricFamily> {
+        let mut families = ScrapeResult::new(self.metrics, self.stats).into_metric_families();
+        if let (Some(age), Some(last_success)) = (self.age, self.last_success) {
+            families.push(MetricFamily::new("json_exporter_cache_age_seconds", "Age of the last successful background poll", MetricType::Gauge)
+                .with_sample(vec!(), age.as_secs_f64()));
+            families.push(MetricFamily::new("json_exporter_last_success_timestamp_seconds", "Timestamp of the last successful background poll", MetricType::Gauge)
+                .with_sample(vec!(), last_success));
+        }
+        families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::exposition::{ExpositionFormat, MetricFamily};
+    use crate::prom_metric::MetricType;
+    use crate::scrape_result::ScrapeStats;
+    use super::PollCache;
+
+    fn gauge(name: &str, value: f64) -> Option<Vec<MetricFamily>> {
+        Some(vec![MetricFamily::new(name, "Value converted from JSON", MetricType::Gauge).with_sample(vec!(), value)])
+    }
+
+    fn render(cache: &PollCache, module_name: &str) -> String {
+        ExpositionFormat::Prometheus.render(&cache.get(module_name).into_metric_families())
+    }
+
+    fn sample_names(output: &str) -> Vec<&str> {
+        output.lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split(' ').next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn missing_module_reports_failure() {
+        let cache = PollCache::new(Duration::from_secs(60));
+        assert_eq!(render(&cache, "default").lines().last(), Some("json_exporter_up 0"));
+    }
+
+    #[test]
+    fn fresh_result_gets_served() {
+        let cache = PollCache::new(Duration::from_secs(60));
+        cache.update("default", gauge("uptime", 42.0), ScrapeStats::default());
+        let output = render(&cache, "default");
+
+        assert_eq!(sample_names(&output), vec![
+            "uptime",
+            "json_exporter_up",
+            "json_exporter_scrape_duration_seconds",
+            "json_exporter_scrape_error{phase=\"fetch\"}",
+            "json_exporter_scrape_error{phase=\"parse\"}",
+            "json_exporter_scrape_error{phase=\"selector\"}",
+            "json_exporter_scrape_error{phase=\"include\"}",
+            "json_exporter_scrape_error{phase=\"timeout\"}",
+            "json_exporter_cache_age_seconds",
+            "json_exporter_last_success_timestamp_seconds"
+        ]);
+        assert!(output.contains("json_exporter_up 1"));
+    }
+
+    #[test]
+    fn failed_poll_keeps_previous_result() {
+        let cache = PollCache::new(Duration::from_secs(60));
+        cache.update("default", gauge("uptime", 42.0), ScrapeStats::default());
+        cache.update("default", None, ScrapeStats { error_phase: Some("fetch"), ..Default::default() });
+        let output = render(&cache, "default");
+
+        assert!(output.lines().any(|line| line == "uptime 42"));
+        assert!(output.contains("json_exporter_scrape_error{phase=\"fetch\"} 1"));
+    }
+
+    #[test]
+    fn stale_result_reports_failure() {
+        let cache = PollCache::new(Duration::from_secs(0));
+        cache.update("default", gauge("uptime", 42.0), ScrapeStats::default());
+        std::thread::sleep(Duration::from_millis(5));
+        let output = render(&cache, "default");
+
+        assert!(!output.contains("uptime 42"));
+        assert!(output.contains("json_exporter_up 0"));
+        assert!(output.contains("json_exporter_cache_age_seconds"));
+    }
+
+    #[test]
+    fn retain_modules_drops_removed_modules() {
+        let cache = PollCache::new(Duration::from_secs(60));
+        cache.update("default", gauge("uptime", 42.0), ScrapeStats::default());
+        cache.update("router", gauge("uplinks", 2.0), ScrapeStats::default());
+        cache.retain_modules(&[&"router".to_string()]);
+
+        assert!(render(&cache, "default").contains("json_exporter_up 0"));
+        assert!(render(&cache, "router").contains("json_exporter_up 1"));
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::exposition::MetricFamily;
+use crate::prom_metric::MetricType;
+
+/// Outcome of a single `/probe` request.
+/// Mirrors blackbox_exporter: the converted metrics (if any)
+/// are followed by `probe_success` and `probe_duration_seconds`
+pub struct ProbeResult {
+    metrics: Option<Vec<MetricFamily>>,
+    duration: Duration
+}
+
+impl ProbeResult {
+    pub fn new(metrics: Option<Vec<MetricFamily>>, duration: Duration) -> Self {
+        Self {
+            metrics,
+            duration
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.metrics.is_some()
+    }
+
+    pub fn into_metric_families(self) -> Vec<MetricFamily> {
+        let is_success = self.is_success();
+        let mut families = self.metrics.unwrap_or_default();
+        families.push(MetricFamily::new("probe_success", "Displays whether or not the probe was a success", MetricType::Gauge)
+            .with_sample(vec!(), if is_success { 1.0 } else { 0.0 }));
+        families.push(MetricFamily::new("probe_duration_seconds", "Returns how long the probe took to complete in seconds", MetricType::Gauge)
+            .with_sample(vec!(), self.duration.as_secs_f64()));
+        families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::exposition::{ExpositionFormat, MetricFamily};
+    use crate::prom_metric::MetricType;
+    use super::ProbeResult;
+
+    fn render(result: ProbeResult) -> String {
+        ExpositionFormat::Prometheus.render(&result.into_metric_families())
+    }
+
+    fn sample_lines(output: &str) -> Vec<&str> {
+        output.lines().filter(|line| !line.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn successful_probe_appends_probe_metrics() {
+        let result = ProbeResult::new(Some(vec![
+            MetricFamily::new("last_refresh_epoch", "Value of last_refresh_epoch converted from JSON", MetricType::Gauge).with_sample(vec!(), 1631046901.0)
+        ]), Duration::from_millis(250));
+        assert_eq!(sample_lines(&render(result)), vec![
+            "last_refresh_epoch 1631046901",
+            "probe_success 1",
+            "probe_duration_seconds 0.25"
+        ]);
+    }
+
+    #[test]
+    fn failed_probe_only_has_probe_metrics() {
+        let result = ProbeResult::new(None, Duration::from_secs(2));
+        assert_eq!(sample_lines(&render(result)), vec![
+            "probe_success 0",
+            "probe_duration_seconds 2"
+        ]);
+    }
+
+    #[test]
+    fn probe_metrics_have_metadata() {
+        let result = ProbeResult::new(None, Duration::from_secs(2));
+        let output = render(result);
+        assert!(output.lines().any(|line| line == "# TYPE probe_success gauge"));
+        assert!(output.lines().any(|line| line == "# TYPE probe_duration_seconds gauge"));
+    }
+}
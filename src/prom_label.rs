@@ -7,19 +7,33 @@ pub struct PromLabel {
 impl PromLabel {
     pub fn new (name: String, value: String) -> Self {
         Self {
-            name: name,
-            value: value
+            name,
+            value
         }
     }
 }
 
-impl ToString for PromLabel {
-    fn to_string(&self) -> String {
-        if self.value.parse::<i64>().is_ok() || self.value.parse::<f64>().is_ok() || self.value.parse::<bool>().is_ok() {
-            format!("{}={}", self.name, self.value).to_string()
-        } else {
-            format!("{}=\"{}\"", self.name, self.value).to_string()
-        }
+/// Suffixes a label name already used by one of `labels` with `_2`, `_3`, ...
+/// so a sample never carries the same label name twice
+pub fn unique_label_name(labels: &[PromLabel], label_name: String) -> String {
+    let is_taken = |name: &str| labels.iter().any(|label| label.name == name);
+    if !is_taken(&label_name) {
+        return label_name
+    }
+    (2..).map(|suffix| format!("{}_{}", label_name, suffix))
+        .find(|name| !is_taken(name))
+        .unwrap()
+}
+
+/// Label values are always quoted, backslashes, double quotes
+/// and line feeds get escaped as required by the exposition format
+impl std::fmt::Display for PromLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escaped_value = self.value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        write!(f, "{}=\"{}\"", self.name, escaped_value)
     }
 }
 
@@ -36,18 +50,24 @@ mod tests {
     #[test]
     fn test_int_number_value() {
         let label = PromLabel::new("foo".to_string(), "34".to_string());
-        assert_eq!(format!("{}={}","foo", "34"), label.to_string());
+        assert_eq!(format!("{}=\"{}\"","foo", "34"), label.to_string());
     }
 
     #[test]
     fn test_float_number_value() {
         let label = PromLabel::new("foo".to_string(), "3.14".to_string());
-        assert_eq!(format!("{}={}","foo", "3.14"), label.to_string());
+        assert_eq!(format!("{}=\"{}\"","foo", "3.14"), label.to_string());
     }
 
     #[test]
     fn test_bool_value() {
         let label = PromLabel::new("foo".to_string(), false.to_string());
-        assert_eq!(format!("{}={}","foo", "false"), label.to_string());
+        assert_eq!(format!("{}=\"{}\"","foo", "false"), label.to_string());
+    }
+
+    #[test]
+    fn test_escaped_value() {
+        let label = PromLabel::new("foo".to_string(), "C:\\Temp \"quoted\"\nsecond line".to_string());
+        assert_eq!(r#"foo="C:\\Temp \"quoted\"\nsecond line""#, label.to_string());
     }
 }
\ No newline at end of file
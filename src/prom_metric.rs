@@ -1,18 +1,74 @@
+use serde::Deserialize;
+
 use crate::prom_label::PromLabel;
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricType {
+    Gauge,
+    Counter,
+    Untyped,
+    /// One 0/1 sample per state, the state is the last label of every sample
+    #[serde(skip_deserializing)]
+    StateSet,
+    /// Constant 1, labels carry the information. Named with an `_info` suffix
+    #[serde(skip_deserializing)]
+    Info,
+    /// `_bucket` samples labeled with their upper bound `le`, `_sum` and `_count`
+    Histogram,
+    /// Samples labeled with their `quantile`, `_sum` and `_count`
+    Summary
+}
+
+impl std::fmt::Display for MetricType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetricType::Gauge => write!(f, "gauge"),
+            MetricType::Counter => write!(f, "counter"),
+            MetricType::Untyped => write!(f, "untyped"),
+            MetricType::StateSet => write!(f, "stateset"),
+            MetricType::Info => write!(f, "info"),
+            MetricType::Histogram => write!(f, "histogram"),
+            MetricType::Summary => write!(f, "summary"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PromMetric {
     pub name: String,
-    pub value: Option<i64>,
-    pub labels: Option<Vec<PromLabel>>
+    pub value: Option<f64>,
+    pub labels: Option<Vec<PromLabel>>,
+    pub metric_type: MetricType,
+    pub help: Option<String>,
+    /// Appended to the family name, such as `_bucket` or `_count`
+    pub suffix: &'static str
 }
 
 impl PromMetric {
-    pub fn new(name: String, value: Option<i64>, labels: Option<Vec<PromLabel>>) -> Self {
+    pub fn new(name: String, value: Option<f64>, labels: Option<Vec<PromLabel>>) -> Self {
         Self {
-            name: name,
-            value: value,
-            labels: labels
+            name,
+            value,
+            labels,
+            metric_type: MetricType::Gauge,
+            help: None,
+            suffix: ""
         }
     }
+
+    pub fn with_type(mut self, metric_type: MetricType) -> Self {
+        self.metric_type = metric_type;
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    pub fn with_suffix(mut self, suffix: &'static str) -> Self {
+        self.suffix = suffix;
+        self
+    }
 }
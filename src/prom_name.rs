@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+/// How names that are not valid Prometheus metric or label names get fixed
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSanitization {
+    /// Replaces every invalid character with `_`
+    #[default]
+    Replace,
+    /// Removes every invalid character
+    Strip
+}
+
+/// Metric names must match `[a-zA-Z_:][a-zA-Z0-9_:]*`
+pub fn sanitize_metric_name(name: &str, strategy: NameSanitization) -> String {
+    sanitize(name, strategy, |c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Label names must match `[a-zA-Z_][a-zA-Z0-9_]*`
+pub fn sanitize_label_name(name: &str, strategy: NameSanitization) -> String {
+    sanitize(name, strategy, |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Label names starting with `__` are reserved for internal use by Prometheus
+pub fn is_reserved_label_name(name: &str) -> bool {
+    name.starts_with("__")
+}
+
+fn sanitize<F: Fn(char) -> bool>(name: &str, strategy: NameSanitization, is_valid_char: F) -> String {
+    let mut sanitized = match strategy {
+        NameSanitization::Replace => name.chars()
+            .map(|c| if is_valid_char(c) { c } else { '_' })
+            .collect::<String>(),
+        NameSanitization::Strip => name.chars()
+            .filter(|c| is_valid_char(*c))
+            .collect::<String>()
+    };
+
+    if sanitized.is_empty() || sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_reserved_label_name, sanitize_label_name, sanitize_metric_name, NameSanitization};
+
+    #[test]
+    fn valid_names_are_unchanged() {
+        assert_eq!(sanitize_metric_name("http_requests:rate5m", NameSanitization::Replace), "http_requests:rate5m");
+        assert_eq!(sanitize_label_name("status_upstream", NameSanitization::Replace), "status_upstream");
+    }
+
+    #[test]
+    fn replace_invalid_characters() {
+        assert_eq!(sanitize_metric_name("router.backend status", NameSanitization::Replace), "router_backend_status");
+        assert_eq!(sanitize_label_name("node:id", NameSanitization::Replace), "node_id");
+    }
+
+    #[test]
+    fn strip_invalid_characters() {
+        assert_eq!(sanitize_metric_name("router.backend-status", NameSanitization::Strip), "routerbackendstatus");
+        assert_eq!(sanitize_label_name("node:id", NameSanitization::Strip), "nodeid");
+    }
+
+    #[test]
+    fn leading_digits_get_prefixed() {
+        assert_eq!(sanitize_metric_name("5xx_count", NameSanitization::Replace), "_5xx_count");
+        assert_eq!(sanitize_label_name("0", NameSanitization::Strip), "_0");
+        assert_eq!(sanitize_label_name("...", NameSanitization::Strip), "_");
+    }
+
+    #[test]
+    fn reserved_label_names() {
+        assert!(is_reserved_label_name("__name__"));
+        assert!(!is_reserved_label_name("_name"));
+    }
+}
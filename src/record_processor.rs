@@ -0,0 +1,75 @@
+use serde_json::Value;
+
+use crate::config_file::{Records, ValueMappings};
+use crate::jq::Jq;
+use crate::prom_label::PromLabel;
+use crate::payload_error::PayloadError;
+use crate::prom_metric::{MetricType, PromMetric};
+use crate::rule_error::RuleErrors;
+use crate::selector_error::SelectorError;
+use crate::utils;
+
+/// Converts every element of an array payload into one sample.
+/// Records without a name or a numeric value are skipped,
+/// records whose selectors fail are reported as failures of the `records` rule
+pub struct RecordProcessor<'a> {
+    config: &'a Records,
+    value_mappings: Option<&'a ValueMappings>,
+    jq: &'a Jq,
+    global_labels: &'a Option<Vec<PromLabel>>
+}
+
+impl<'a> RecordProcessor<'a> {
+    pub fn new(config: &'a Records, value_mappings: Option<&'a ValueMappings>, jq: &'a Jq, global_labels: &'a Option<Vec<PromLabel>>) -> Self {
+        Self {
+            config,
+            value_mappings,
+            jq,
+            global_labels
+        }
+    }
+
+    pub fn process(&self, records: &[Value], rule_errors: &RuleErrors) -> Result<Vec<PromMetric>, PayloadError> {
+        let mut metrics = vec!();
+
+        for record in records {
+            if let Some(metric) = rule_errors.isolate("records", self.record_to_metric(record))?.flatten() {
+                metrics.push(metric);
+            }
+        }
+
+        Ok(metrics)
+    }
+
+    fn record_to_metric(&self, record: &Value) -> Result<Option<PromMetric>, SelectorError> {
+        let name = self.resolve_first(record, self.config.name.as_deref().unwrap_or(".name"))?
+            .and_then(|name| utils::json_value_to_str(&name))
+            .filter(|name| !name.is_empty());
+        let value = self.resolve_first(record, self.config.value.as_deref().unwrap_or(".value"))?
+            .and_then(|value| utils::parse_json_value(&value, self.config.value_parser, self.value_mappings));
+
+        let (name, value) = match (name, value) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return Ok(None)
+        };
+
+        let mut labels = self.global_labels.clone().unwrap_or_default();
+        for (label_name, label_selector) in self.config.labels.iter().flatten() {
+            let label_value = self.resolve_first(record, label_selector)?
+                .and_then(|label_value| utils::json_value_to_str(&label_value))
+                .unwrap_or_default();
+            labels.push(PromLabel::new(label_name.to_string(), label_value));
+        }
+
+        let metric_labels = if labels.is_empty() { None } else { Some(labels) };
+        Ok(Some(PromMetric::new(name, Some(value), metric_labels)
+            .with_type(self.config.metric_type.unwrap_or(MetricType::Gauge))
+            .with_help(self.config.help.clone())))
+    }
+
+    fn resolve_first(&self, record: &Value, selector: &str) -> Result<Option<Value>, SelectorError> {
+        self.jq.resolve(record, selector)
+            .map(|values| values.into_iter().find(|value| !value.is_null()))
+            .map_err(|err| SelectorError::new(&format!("Failed to resolve record selector {}", selector), Some(err)))
+    }
+}
@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::exposition::MetricFamily;
+use crate::payload_error::PayloadError;
+use crate::prom_label::PromLabel;
+use crate::prom_metric::MetricType;
+
+/// A conversion rule (global label, include, metric mapping or records) that failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleError {
+    pub rule: String,
+    pub reason: &'static str
+}
+
+/// Collects the rules that failed during one conversion.
+/// Failed rules get skipped so every other rule still gets converted,
+/// strict modules fail the whole conversion instead.
+pub struct RuleErrors {
+    strict: bool,
+    errors: RefCell<Vec<RuleError>>
+}
+
+impl RuleErrors {
+    pub fn new(strict: bool) -> Self {
+        Self {
+            strict,
+            errors: RefCell::new(vec!())
+        }
+    }
+
+    /// Returns `None` for a skipped rule. Timeouts always abort the conversion
+    pub fn isolate<T, E: Into<PayloadError>>(&self, rule: &str, result: Result<T, E>) -> Result<Option<T>, PayloadError> {
+        match result.map_err(Into::into) {
+            Ok(value) => Ok(Some(value)),
+            Err(PayloadError::TimeoutError) => Err(PayloadError::TimeoutError),
+            Err(err) if self.strict => Err(err),
+            Err(err) => {
+                eprintln!("ERR skipping rule {}: {}", rule, err);
+                self.errors.borrow_mut().push(RuleError { rule: rule.to_string(), reason: err.reason() });
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn errors(&self) -> Vec<RuleError> {
+        self.errors.borrow().clone()
+    }
+}
+
+/// Number of skipped rules per module, rule and reason since the exporter started
+#[derive(Default)]
+pub struct RuleErrorCounter {
+    counts: Mutex<BTreeMap<(String, String, &'static str), u64>>
+}
+
+impl RuleErrorCounter {
+    pub fn record(&self, module_name: &str, errors: &[RuleError]) {
+        let mut counts = self.counts.lock().unwrap();
+        for error in errors {
+            *counts.entry((module_name.to_string(), error.rule.to_string(), error.reason)).or_default() += 1;
+        }
+    }
+
+    /// Counts of a single module, appended to `/metrics`
+    pub fn metrics(&self, module_name: &str) -> MetricFamily {
+        let counts = self.counts.lock().unwrap();
+        let mut family = MetricFamily::new("json_exporter_rule_errors_total", "Number of conversion rules skipped because they failed", MetricType::Counter);
+        for ((_, rule, reason), count) in counts.iter().filter(|((module, _, _), _)| module == module_name) {
+            let labels = vec![
+                PromLabel::new("rule".to_string(), rule.to_string()),
+                PromLabel::new("reason".to_string(), reason.to_string())
+            ];
+            family = family.with_sample(labels, *count as f64);
+        }
+        family
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exposition::ExpositionFormat;
+    use crate::payload_error::PayloadError;
+    use crate::selector_error::SelectorError;
+    use super::{RuleError, RuleErrorCounter, RuleErrors};
+
+    fn failed_rule() -> Result<u64, SelectorError> {
+        Err(SelectorError::new("Label selector yields 2 values, expected 1", None))
+    }
+
+    #[test]
+    fn isolate_skips_failed_rules() {
+        let errors = RuleErrors::new(false);
+
+        assert_eq!(errors.isolate("metric:uplinks", Ok::<_, SelectorError>(2)).unwrap(), Some(2));
+        assert_eq!(errors.isolate("metric:backends", failed_rule()).unwrap(), None);
+        assert_eq!(errors.errors(), vec![RuleError { rule: "metric:backends".to_string(), reason: "unexpected_value" }]);
+    }
+
+    #[test]
+    fn isolate_in_strict_mode_returns_error() {
+        let errors = RuleErrors::new(true);
+
+        assert!(errors.isolate("metric:backends", failed_rule()).is_err());
+        assert!(errors.errors().is_empty());
+    }
+
+    #[test]
+    fn isolate_never_skips_timeouts() {
+        let errors = RuleErrors::new(false);
+        assert!(errors.isolate("records", Err::<u64, _>(PayloadError::TimeoutError)).is_err());
+    }
+
+    #[test]
+    fn counter_reports_counts_of_module() {
+        let counter = RuleErrorCounter::default();
+        let error = RuleError { rule: "include:router_status".to_string(), reason: "missing_field" };
+        counter.record("default", &[error.clone(), error.clone()]);
+        counter.record("router", &[error]);
+
+        let samples = ExpositionFormat::Prometheus.render(&[counter.metrics("default")])
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(samples, vec!["json_exporter_rule_errors_total{rule=\"include:router_status\",reason=\"missing_field\"} 2"]);
+    }
+}
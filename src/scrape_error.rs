@@ -0,0 +1,50 @@
+use crate::fetch_error::FetchError;
+use crate::payload_error::PayloadError;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ScrapeError {
+    FetchError(FetchError),
+    PayloadError(PayloadError),
+    FormatError(String),
+    TimeoutError
+}
+
+impl ScrapeError {
+    /// Phase of the scrape that failed, reported by `json_exporter_scrape_error`
+    pub fn phase(&self) -> &'static str {
+        match self {
+            ScrapeError::FetchError(_) => "fetch",
+            ScrapeError::FormatError(_) | ScrapeError::PayloadError(PayloadError::JsonError(_)) => "parse",
+            ScrapeError::PayloadError(PayloadError::SelectorError(_)) | ScrapeError::PayloadError(PayloadError::NonScalarValueError(_)) => "selector",
+            ScrapeError::PayloadError(PayloadError::IncludeError(_)) | ScrapeError::PayloadError(PayloadError::MissingFieldError(_)) => "include",
+            ScrapeError::PayloadError(PayloadError::TimeoutError) | ScrapeError::TimeoutError => "timeout"
+        }
+    }
+}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapeError::FetchError(e) => write!(f, "{}", e),
+            ScrapeError::PayloadError(e) => write!(f, "{}", e),
+            ScrapeError::FormatError(e) => write!(f, "{}", e),
+            ScrapeError::TimeoutError => write!(f, "Scrape timed out"),
+        }
+    }
+}
+
+impl From<FetchError> for ScrapeError {
+    fn from(err: FetchError) -> Self {
+        ScrapeError::FetchError(err)
+    }
+}
+
+impl From<PayloadError> for ScrapeError {
+    fn from(err: PayloadError) -> Self {
+        match err {
+            PayloadError::TimeoutError => ScrapeError::TimeoutError,
+            _ => ScrapeError::PayloadError(err)
+        }
+    }
+}
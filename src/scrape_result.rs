@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use crate::exposition::MetricFamily;
+use crate::prom_label::PromLabel;
+use crate::prom_metric::MetricType;
+use crate::rule_error::RuleError;
+
+/// Phases a scrape can fail in, see `ScrapeError::phase`
+pub const SCRAPE_PHASES: [&str; 5] = ["fetch", "parse", "selector", "include", "timeout"];
+
+/// Outcome of a single scrape, reported along with the converted metrics
+#[derive(Clone, Debug, Default)]
+pub struct ScrapeStats {
+    pub duration: Duration,
+    /// Only known for HTTP sources that responded
+    pub http_status: Option<u16>,
+    pub response_size: Option<usize>,
+    pub error_phase: Option<&'static str>,
+    /// Rules skipped during the conversion, counted separately by `RuleErrorCounter`
+    pub rule_errors: Vec<RuleError>
+}
+
+impl ScrapeStats {
+    pub fn metric_families(&self) -> Vec<MetricFamily> {
+        let mut families = vec![
+            MetricFamily::new("json_exporter_scrape_duration_seconds", "Time it took to fetch and convert the document", MetricType::Gauge)
+                .with_sample(vec!(), self.duration.as_secs_f64())
+        ];
+        if let Some(http_status) = self.http_status {
+            families.push(MetricFamily::new("json_exporter_upstream_http_status", "HTTP status code the upstream responded with", MetricType::Gauge)
+                .with_sample(vec!(), http_status.into()));
+        }
+        if let Some(response_size) = self.response_size {
+            families.push(MetricFamily::new("json_exporter_response_size_bytes", "Size of the fetched document", MetricType::Gauge)
+                .with_sample(vec!(), response_size as f64));
+        }
+        let mut scrape_error = MetricFamily::new("json_exporter_scrape_error", "Whether the scrape failed in this phase", MetricType::Gauge);
+        for phase in SCRAPE_PHASES.iter() {
+            let labels = vec![PromLabel::new("phase".to_string(), phase.to_string())];
+            scrape_error = scrape_error.with_sample(labels, if self.error_phase == Some(phase) { 1.0 } else { 0.0 });
+        }
+        families.push(scrape_error);
+        families
+    }
+}
+
+/// Outcome of a single `/metrics` request.
+/// The converted metrics (if any) are followed by `json_exporter_up` and the scrape stats
+pub struct ScrapeResult {
+    metrics: Option<Vec<MetricFamily>>,
+    stats: Option<ScrapeStats>
+}
+
+impl ScrapeResult {
+    pub fn new(metrics: Option<Vec<MetricFamily>>, stats: Option<ScrapeStats>) -> Self {
+        Self {
+            metrics,
+            stats
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.metrics.is_some()
+    }
+
+    pub fn into_metric_families(self) -> Vec<MetricFamily> {
+        let is_success = self.is_success();
+        let mut families = self.metrics.unwrap_or_default();
+        families.push(MetricFamily::new("json_exporter_up", "Whether the JSON endpoint was fetched and converted in time", MetricType::Gauge)
+            .with_sample(vec!(), if is_success { 1.0 } else { 0.0 }));
+        if let Some(stats) = &self.stats {
+            families.append(&mut stats.metric_families());
+        }
+        families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::exposition::{ExpositionFormat, MetricFamily};
+    use crate::prom_metric::MetricType;
+    use super::{ScrapeResult, ScrapeStats};
+
+    fn sample_lines(result: ScrapeResult) -> Vec<String> {
+        ExpositionFormat::Prometheus.render(&result.into_metric_families())
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn successful_scrape_appends_up_and_stats() {
+        let stats = ScrapeStats {
+            duration: Duration::from_millis(250),
+            http_status: Some(200),
+            response_size: Some(1234),
+            error_phase: None,
+            rule_errors: vec!()
+        };
+        let result = ScrapeResult::new(Some(vec![
+            MetricFamily::new("last_refresh_epoch", "Value of last_refresh_epoch converted from JSON", MetricType::Gauge).with_sample(vec!(), 1631046901.0)
+        ]), Some(stats));
+        assert_eq!(sample_lines(result), vec![
+            "last_refresh_epoch 1631046901",
+            "json_exporter_up 1",
+            "json_exporter_scrape_duration_seconds 0.25",
+            "json_exporter_upstream_http_status 200",
+            "json_exporter_response_size_bytes 1234",
+            "json_exporter_scrape_error{phase=\"fetch\"} 0",
+            "json_exporter_scrape_error{phase=\"parse\"} 0",
+            "json_exporter_scrape_error{phase=\"selector\"} 0",
+            "json_exporter_scrape_error{phase=\"include\"} 0",
+            "json_exporter_scrape_error{phase=\"timeout\"} 0"
+        ]);
+    }
+
+    #[test]
+    fn failed_fetch_reports_fetch_phase() {
+        let stats = ScrapeStats {
+            duration: Duration::from_secs(2),
+            error_phase: Some("fetch"),
+            ..Default::default()
+        };
+        let result = ScrapeResult::new(None, Some(stats));
+        assert_eq!(sample_lines(result), vec![
+            "json_exporter_up 0",
+            "json_exporter_scrape_duration_seconds 2",
+            "json_exporter_scrape_error{phase=\"fetch\"} 1",
+            "json_exporter_scrape_error{phase=\"parse\"} 0",
+            "json_exporter_scrape_error{phase=\"selector\"} 0",
+            "json_exporter_scrape_error{phase=\"include\"} 0",
+            "json_exporter_scrape_error{phase=\"timeout\"} 0"
+        ]);
+    }
+
+    #[test]
+    fn scrape_without_stats_only_has_up_metric() {
+        let result = ScrapeResult::new(None, None);
+        assert_eq!(sample_lines(result), vec!["json_exporter_up 0"]);
+    }
+}
@@ -0,0 +1,63 @@
+use rocket::request::{FromRequest, Outcome, Request};
+use std::time::Duration;
+
+/// Used when neither the module nor Prometheus define a timeout
+pub const DEFAULT_TIMEOUT_SECONDS: f64 = 10.0;
+
+/// Scrape timeout Prometheus sends with every request
+#[derive(Default)]
+pub struct ScrapeTimeout(Option<f64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ScrapeTimeout {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let timeout = request.headers()
+            .get_one("X-Prometheus-Scrape-Timeout-Seconds")
+            .and_then(|timeout| timeout.parse::<f64>().ok())
+            .filter(|timeout| *timeout > 0.0 && timeout.is_finite());
+        Outcome::Success(ScrapeTimeout(timeout))
+    }
+}
+
+impl ScrapeTimeout {
+    /// The module timeout, capped by the Prometheus timeout minus `offset`.
+    /// The offset leaves time to send the response before Prometheus gives up
+    pub fn duration(&self, module_timeout: Option<f64>, offset: f64) -> Duration {
+        let prometheus_timeout = self.0.map(|timeout| {
+            if timeout > offset { timeout - offset } else { timeout }
+        });
+
+        let timeout = match (module_timeout, prometheus_timeout) {
+            (Some(module_timeout), Some(prometheus_timeout)) => module_timeout.min(prometheus_timeout),
+            (Some(timeout), None) | (None, Some(timeout)) => timeout,
+            (None, None) => DEFAULT_TIMEOUT_SECONDS
+        };
+        Duration::from_secs_f64(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{ScrapeTimeout, DEFAULT_TIMEOUT_SECONDS};
+
+    #[test]
+    fn duration_without_any_timeout_uses_default() {
+        assert_eq!(ScrapeTimeout(None).duration(None, 0.5), Duration::from_secs_f64(DEFAULT_TIMEOUT_SECONDS));
+    }
+
+    #[test]
+    fn duration_subtracts_offset_from_prometheus_timeout() {
+        assert_eq!(ScrapeTimeout(Some(10.0)).duration(None, 0.5), Duration::from_secs_f64(9.5));
+        assert_eq!(ScrapeTimeout(Some(0.25)).duration(None, 0.5), Duration::from_secs_f64(0.25));
+    }
+
+    #[test]
+    fn duration_is_capped_by_prometheus_timeout() {
+        assert_eq!(ScrapeTimeout(Some(5.0)).duration(Some(30.0), 0.5), Duration::from_secs_f64(4.5));
+        assert_eq!(ScrapeTimeout(Some(15.0)).duration(Some(3.0), 0.5), Duration::from_secs_f64(3.0));
+        assert_eq!(ScrapeTimeout(None).duration(Some(30.0), 0.5), Duration::from_secs_f64(30.0));
+    }
+}
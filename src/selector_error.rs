@@ -1,4 +1,4 @@
-use crate::jq::JqError;
+use crate::jq::{JqError, ValueError};
 
 #[derive(Debug)]
 pub struct SelectorError{
@@ -10,7 +10,20 @@ impl SelectorError {
     pub fn new(message: &str, cause: Option<JqError>) -> Self {
         Self {
             message: message.to_string(),
-            cause: cause
+            cause
+        }
+    }
+
+    /// An object or array where a scalar value is expected
+    pub fn non_scalar_value(message: &str) -> Self {
+        Self::new(message, Some(JqError::NonScalarValueError(ValueError::new(message.to_string()))))
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.cause {
+            Some(JqError::NonScalarValueError(_)) => "non_scalar_value",
+            Some(_) => "selector_failed",
+            None => "unexpected_value"
         }
     }
 }
@@ -19,8 +32,7 @@ impl std::error::Error for SelectorError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         if let Some(cause) = self.cause.as_ref() {
             match cause {
-                JqError::IoError(e) => Some(e),
-                JqError::ParserError(e) => Some(e),
+                JqError::FilterError(e) => Some(e),
                 JqError::NonScalarValueError(e) => Some(e),
             }
         }
@@ -0,0 +1,136 @@
+use tokio::process::Command;
+
+use crate::config_file::Module;
+use crate::fetch_error::FetchError;
+use crate::http_client::HttpClient;
+use crate::input_format::InputFormat;
+
+/// A fetched document, with its format if the source tells it
+#[derive(Debug)]
+pub struct Document {
+    pub body: String,
+    pub format: Option<InputFormat>,
+    pub http_status: Option<u16>
+}
+
+/// Where a JSON document comes from.
+/// `file://` and `exec:` sources are only allowed for the endpoint passed on the command line
+#[derive(Debug, PartialEq)]
+pub enum Source<'a> {
+    Http(&'a str),
+    /// Read on every scrape
+    File(&'a str),
+    /// Run with `sh -c` on every scrape, stdout is the document
+    Exec(&'a str)
+}
+
+impl<'a> Source<'a> {
+    pub fn new(endpoint: &'a str) -> Self {
+        if let Some(path) = endpoint.strip_prefix("file://") {
+            Source::File(path)
+        } else if let Some(command) = endpoint.strip_prefix("exec:") {
+            Source::Exec(command.trim())
+        } else {
+            Source::Http(endpoint)
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        !matches!(self, Source::Http(_))
+    }
+
+    /// The command gets killed when the returned future is dropped, e.g. by a scrape timeout
+    pub async fn fetch(&self, module: &Module) -> Result<Document, FetchError> {
+        match self {
+            Source::Http(url) => HttpClient::new(module).fetch(url).await,
+            Source::File(path) => tokio::fs::read_to_string(path).await
+                .map(|body| Document { body, format: InputFormat::from_path(path), http_status: None })
+                .map_err(|err| FetchError::SourceError(format!("Failed to read {}: {}", path, err))),
+            Source::Exec(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .kill_on_drop(true)
+                    .output()
+                    .await
+                    .map_err(|err| FetchError::SourceError(format!("Failed to run {}: {}", command, err)))?;
+
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    return Err(FetchError::SourceError(format!("Command {} failed with {}: {}", command, output.status, stderr.trim())))
+                }
+                String::from_utf8(output.stdout)
+                    .map(|body| Document { body, format: None, http_status: None })
+                    .map_err(|err| FetchError::SourceError(format!("Command {} printed invalid UTF-8: {}", command, err)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use assert_matches::assert_matches;
+    use crate::config_file::Module;
+    use crate::fetch_error::FetchError;
+    use crate::input_format::InputFormat;
+    use super::Source;
+
+    fn module() -> Module {
+        Module::from_yaml_for_test("global_prefix: local")
+    }
+
+    #[test]
+    fn new_detects_source_by_prefix() {
+        assert_eq!(Source::new("http://localhost:8800/json"), Source::Http("http://localhost:8800/json"));
+        assert_eq!(Source::new("file:///var/run/app/status.json"), Source::File("/var/run/app/status.json"));
+        assert_eq!(Source::new("exec: zpool status -j"), Source::Exec("zpool status -j"));
+        assert!(!Source::new("https://localhost/json").is_local());
+        assert!(Source::new("exec:date").is_local());
+    }
+
+    #[tokio::test]
+    async fn fetch_reads_file_on_every_call() {
+        let path = std::env::temp_dir().join(format!("json_exporter_source_{}.json", std::process::id()));
+        let endpoint = format!("file://{}", path.display());
+        let source = Source::new(&endpoint);
+        fs::write(&path, r#"{"uptime": 0}"#).unwrap();
+        assert_eq!(source.fetch(&module()).await.unwrap().format, Some(InputFormat::Json));
+
+        fs::write(&path, r#"{"uptime": 1}"#).unwrap();
+        assert_eq!(source.fetch(&module()).await.unwrap().body, r#"{"uptime": 1}"#);
+        fs::write(&path, r#"{"uptime": 2}"#).unwrap();
+        assert_eq!(source.fetch(&module()).await.unwrap().body, r#"{"uptime": 2}"#);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fetch_returns_command_output() {
+        let source = Source::new(r#"exec:echo '{"uptime": 42}'"#);
+        assert_eq!(source.fetch(&module()).await.unwrap().body.trim(), r#"{"uptime": 42}"#);
+    }
+
+    #[tokio::test]
+    async fn fetch_with_failing_command_returns_error() {
+        let source = Source::new("exec:echo broken >&2; exit 3");
+        let err = source.fetch(&module()).await.unwrap_err();
+        assert_matches!(&err, FetchError::SourceError(message) if message.contains("broken"));
+    }
+
+    #[tokio::test]
+    async fn fetch_with_missing_file_returns_error() {
+        let source = Source::new("file:///nonexistent/status.json");
+        assert_matches!(source.fetch(&module()).await, Err(FetchError::SourceError(_)));
+    }
+
+    #[tokio::test]
+    async fn dropped_fetch_does_not_wait_for_command() {
+        let module = module();
+        let source = Source::new("exec:sleep 5");
+        let start = Instant::now();
+
+        assert!(tokio::time::timeout(Duration::from_millis(100), source.fetch(&module)).await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,129 @@
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::version::{TLS12, TLS13};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName, SupportedProtocolVersion};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::config_file::{TlsConfig, TlsVersion};
+
+/// Builds the rustls configuration for a `tls_config` section.
+/// Called once per config load, rotated certificates get picked up by reloading the config
+pub fn client_config(config: &TlsConfig) -> Result<ClientConfig, String> {
+    let versions: &[&SupportedProtocolVersion] = match config.min_version {
+        Some(TlsVersion::Tls13) => &[&TLS13],
+        Some(TlsVersion::Tls12) | None => &[&TLS12, &TLS13]
+    };
+
+    let builder = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|err| err.to_string())?
+        .with_custom_certificate_verifier(server_cert_verifier(config)?);
+
+    match (&config.cert_file, &config.key_file) {
+        (Some(cert_file), Some(key_file)) => builder
+            .with_client_auth_cert(read_certificates(cert_file)?, read_private_key(key_file)?)
+            .map_err(|err| format!("Invalid client certificate: {}", err)),
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err("cert_file and key_file must be set together".to_string())
+    }
+}
+
+fn server_cert_verifier(config: &TlsConfig) -> Result<Arc<dyn ServerCertVerifier>, String> {
+    if config.insecure_skip_verify {
+        return Ok(Arc::new(InsecureVerifier))
+    }
+
+    let server_name = config.server_name.as_deref()
+        .map(ServerName::try_from)
+        .transpose()
+        .map_err(|err| format!("Invalid server_name: {}", err))?;
+
+    Ok(Arc::new(ServerNameVerifier {
+        inner: WebPkiVerifier::new(root_store(config)?, None),
+        server_name
+    }))
+}
+
+fn root_store(config: &TlsConfig) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    let certificates = match &config.ca_file {
+        Some(ca_file) => read_certificates(ca_file)?,
+        None => rustls_native_certs::load_native_certs()
+            .map_err(|err| format!("Failed to load system root certificates: {}", err))?
+            .into_iter()
+            .map(|certificate| Certificate(certificate.0))
+            .collect()
+    };
+
+    roots.add_parsable_certificates(&certificates.iter().map(|certificate| &certificate.0).collect::<Vec<_>>());
+    Ok(roots)
+}
+
+fn read_certificates(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|err| format!("Failed to read {}: {}", path, err))?;
+
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in {}", path))
+    }
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &str) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let mut reader = BufReader::new(file);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|err| format!("Failed to read {}: {}", path, err))? {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(format!("No private key found in {}", path))
+        }
+    }
+}
+
+/// Verifies the certificate chain, optionally against `server_name` instead of the URL host
+struct ServerNameVerifier {
+    inner: WebPkiVerifier,
+    server_name: Option<ServerName>
+}
+
+impl ServerCertVerifier for ServerNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let server_name = self.server_name.as_ref().unwrap_or(server_name);
+        self.inner.verify_server_cert(end_entity, intermediates, server_name, scts, ocsp_response, now)
+    }
+}
+
+/// Accepts any server certificate, used for `insecure_skip_verify`
+struct InsecureVerifier;
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
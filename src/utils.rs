@@ -1,50 +1,170 @@
 use serde_json::Value;
 
-pub fn json_number_to_i64(value: &Value) -> Option<i64> {
-    //TODO: Enhance support for other numeric types
-    //such as floats as well as unsigned integers
-    if value.is_i64() || value.is_u64() {
-        Some(value.as_i64().unwrap())
-    }
-    else {
-        None
-    }
+use crate::config_file::ValueMappings;
+use crate::value_parser::ValueParser;
+
+/// Converts any JSON number into an f64.
+/// Unsigned integers beyond `i64::MAX` are kept (with f64 precision)
+pub fn json_number_to_f64(value: &Value) -> Option<f64> {
+    value.as_f64()
 }
 
 pub fn json_value_to_str(value: &Value) -> Option<String> {
     if value.is_string() {
-        return value.as_str().and_then(|str| Some(str.to_string()))
+        value.as_str().map(|str| str.to_string())
     }
     else if value.is_number() {
-        return json_number_to_i64(value).and_then(|num| Some(num.to_string()))
+        value.as_number().map(|num| num.to_string())
     }
     else if value.is_boolean() {
-        return value.as_bool().and_then(|f| Some(f.to_string()))
+        value.as_bool().map(|f| f.to_string())
+    }
+    else {
+        None
     }
-    return None
 }
 
-pub fn json_value_to_i64(value: &Value) -> Option<i64> {
+/// Strings are looked up in `value_mappings` first. Without a match,
+/// "ok" and "error" become 1 and 0 so they can be used as a gauge value
+pub fn json_value_to_f64(value: &Value, value_mappings: Option<&ValueMappings>) -> Option<f64> {
     if value.is_string() {
-        let value_str = value.as_str().unwrap().to_lowercase();
-        //We're testing for special strings
-        //such as "ok" and "error" to convert them into numerical values
-        //so we can use those as a gauge value
-        if value_str == "ok" {
-            return Some(1)
-        }
-        else if value == "error" {
-            return Some(0)
-        }
-        else {
-            return None
+        let value_str = value.as_str().unwrap();
+        if let Some(mapped_value) = value_mappings.and_then(|mappings| mappings.map(value_str)) {
+            return Some(mapped_value)
         }
+
+        let value_str = value_str.to_lowercase();
+        let known_value = match value_str.as_str() {
+            "ok" => Some(1.0),
+            "error" => Some(0.0),
+            _ => special_float_from_str(&value_str)
+        };
+        known_value.or_else(|| value_mappings.and_then(|mappings| mappings.fallback))
     }
     else if value.is_number() {
-        return value.as_i64().and_then(|v| Some(v))
+        json_number_to_f64(value)
     }
     else if value.is_boolean() {
-        return value.as_bool().and_then(|f| Some(f as i64))
+        value.as_bool().map(|f| f as i64 as f64)
+    }
+    else {
+        None
+    }
+}
+
+/// Converts with `value_parser` when one is configured, like `json_value_to_f64` otherwise
+pub fn parse_json_value(value: &Value, value_parser: Option<ValueParser>, value_mappings: Option<&ValueMappings>) -> Option<f64> {
+    match value_parser {
+        Some(value_parser) => value_parser.parse(value),
+        None => json_value_to_f64(value, value_mappings)
+    }
+}
+
+/// JSON has no representation for infinity and NaN,
+/// APIs usually report them as strings instead
+fn special_float_from_str(value: &str) -> Option<f64> {
+    match value {
+        "nan" => Some(f64::NAN),
+        "inf" | "+inf" | "infinity" | "+infinity" => Some(f64::INFINITY),
+        "-inf" | "-infinity" => Some(f64::NEG_INFINITY),
+        _ => None
+    }
+}
+
+/// Formats a sample value the way the exposition format expects it.
+/// Very large and very small values use the exponent form instead of dozens of digits
+pub fn format_metric_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    }
+    else if value == f64::INFINITY {
+        "+Inf".to_string()
     }
-    return None
-}
\ No newline at end of file
+    else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    }
+    else if value != 0.0 && (value.abs() >= 1e15 || value.abs() < 1e-4) {
+        format!("{:e}", value)
+    }
+    else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::config_file::ValueMappings;
+    use super::{format_metric_value, json_value_to_f64, json_value_to_str};
+
+    fn value_mappings() -> ValueMappings {
+        serde_yaml::from_str(r#"
+values:
+  green: 2
+  yellow: 1
+  red: 0
+patterns:
+  - regex: "^(UP|RUNNING)"
+    value: 1
+fallback: -1
+"#).unwrap()
+    }
+
+    #[test]
+    fn convert_floats() {
+        assert_eq!(json_value_to_f64(&json!(0.25), None), Some(0.25));
+        assert_eq!(json_value_to_f64(&json!(-3), None), Some(-3.0));
+    }
+
+    #[test]
+    fn convert_ok_and_error_strings() {
+        assert_eq!(json_value_to_f64(&json!("OK"), None), Some(1.0));
+        assert_eq!(json_value_to_f64(&json!("Error"), None), Some(0.0));
+        assert_eq!(json_value_to_f64(&json!("degraded"), None), None);
+    }
+
+    #[test]
+    fn convert_mapped_strings() {
+        let mappings = value_mappings();
+        assert_eq!(json_value_to_f64(&json!("Green"), Some(&mappings)), Some(2.0));
+        assert_eq!(json_value_to_f64(&json!("red"), Some(&mappings)), Some(0.0));
+        assert_eq!(json_value_to_f64(&json!("RUNNING since 3d"), Some(&mappings)), Some(1.0));
+        assert_eq!(json_value_to_f64(&json!("ok"), Some(&mappings)), Some(1.0));
+        assert_eq!(json_value_to_f64(&json!("stopped"), Some(&mappings)), Some(-1.0));
+        assert_eq!(json_value_to_f64(&json!(3), Some(&mappings)), Some(3.0));
+    }
+
+    #[test]
+    fn convert_large_unsigned_integers() {
+        assert_eq!(json_value_to_f64(&json!(u64::MAX), None), Some(u64::MAX as f64));
+        assert_eq!(json_value_to_str(&json!(u64::MAX)), Some(u64::MAX.to_string()));
+    }
+
+    #[test]
+    fn convert_special_float_strings() {
+        assert!(json_value_to_f64(&json!("NaN"), None).unwrap().is_nan());
+        assert_eq!(json_value_to_f64(&json!("+Inf"), None), Some(f64::INFINITY));
+        assert_eq!(json_value_to_f64(&json!("-Infinity"), None), Some(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn format_values() {
+        assert_eq!(format_metric_value(1631046901.0), "1631046901");
+        assert_eq!(format_metric_value(0.125), "0.125");
+        assert_eq!(format_metric_value(f64::NAN), "NaN");
+        assert_eq!(format_metric_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_metric_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_metric_value(0.0), "0");
+    }
+
+    #[test]
+    fn format_large_and_small_values_with_exponent() {
+        assert_eq!(format_metric_value(999999999999999.0), "999999999999999");
+        assert_eq!(format_metric_value(1e15), "1e15");
+        assert_eq!(format_metric_value(u64::MAX as f64), "1.8446744073709552e19");
+        assert_eq!(format_metric_value(-2.5e20), "-2.5e20");
+        assert_eq!(format_metric_value(0.0001), "0.0001");
+        assert_eq!(format_metric_value(0.00005), "5e-5");
+        assert_eq!(format_metric_value(-1.25e-9), "-1.25e-9");
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::utils;
+
+/// Parses values that APIs encode as strings and normalizes them to base units.
+/// Numbers are taken as already being in the base unit, except for `percent`
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueParser {
+    /// `3d4h12m`, `1.5s`, `250ms` or `12:30:00` to seconds
+    Duration,
+    /// `512MiB`, `1.5 GB` or `64k` to bytes
+    Bytes,
+    /// `73%` or `73` to the ratio 0.73
+    Percent,
+    /// RFC 3339 timestamps or dates to Unix epoch seconds
+    Timestamp,
+    /// Seconds elapsed since a timestamp, to alert on stale data
+    Age
+}
+
+impl ValueParser {
+    pub fn parse(self, value: &Value) -> Option<f64> {
+        let text = match value {
+            Value::String(text) => text.trim(),
+            _ => return self.parse_number(utils::json_number_to_f64(value)?)
+        };
+        if let Ok(number) = text.parse::<f64>() {
+            return self.parse_number(number)
+        }
+
+        match self {
+            ValueParser::Duration => parse_duration(text),
+            ValueParser::Bytes => parse_bytes(text),
+            ValueParser::Percent => text.strip_suffix('%')?.trim().parse::<f64>().ok().map(|percent| percent / 100.0),
+            ValueParser::Timestamp => parse_timestamp(text),
+            ValueParser::Age => parse_timestamp(text).map(seconds_since)
+        }
+    }
+
+    fn parse_number(self, number: f64) -> Option<f64> {
+        match self {
+            ValueParser::Percent => Some(number / 100.0),
+            ValueParser::Age => Some(seconds_since(number)),
+            _ => Some(number)
+        }
+    }
+}
+
+fn seconds_since(timestamp: f64) -> f64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |duration| duration.as_secs_f64());
+    now - timestamp
+}
+
+/// Either a sequence of numbers with units or `[hh:]mm:ss`
+fn parse_duration(text: &str) -> Option<f64> {
+    if text.contains(':') {
+        return text.split(':').try_fold(0.0, |seconds, part| Some(seconds * 60.0 + part.trim().parse::<f64>().ok()?))
+    }
+
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1.0, text),
+        None => (1.0, text)
+    };
+    let mut seconds = 0.0;
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (number, after_number) = split_number(rest)?;
+        let unit_length = after_number.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(after_number.len());
+        let (unit, after_unit) = after_number.split_at(unit_length);
+        seconds += number * duration_unit_seconds(unit.trim().trim_end_matches(',').trim())?;
+        rest = after_unit.trim_start();
+    }
+    Some(sign * seconds)
+}
+
+fn duration_unit_seconds(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "ns" => Some(1e-9),
+        "us" | "µs" => Some(1e-6),
+        "ms" => Some(1e-3),
+        "" | "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(3600.0),
+        "d" | "day" | "days" => Some(86400.0),
+        "w" | "week" | "weeks" => Some(604800.0),
+        _ => None
+    }
+}
+
+/// Decimal prefixes (`kB`, `MB`) are powers of 1000, binary ones (`KiB`, `MiB`) and
+/// bare letters (`k`, `M`, as used by the JVM) are powers of 1024
+fn parse_bytes(text: &str) -> Option<f64> {
+    let (number, unit) = split_number(text)?;
+    let unit = unit.trim().to_lowercase();
+    let unit = unit.strip_suffix("bytes").or_else(|| unit.strip_suffix("byte")).unwrap_or(&unit);
+    let exponent = match unit.trim_end_matches('b').trim_end_matches('i') {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        "p" => 5,
+        "e" => 6,
+        _ => return None
+    };
+    let is_decimal = unit.len() == 2 && unit.ends_with('b');
+    let base: f64 = if is_decimal { 1000.0 } else { 1024.0 };
+    Some(number * base.powi(exponent))
+}
+
+/// Splits a leading decimal number off `text`
+fn split_number(text: &str) -> Option<(f64, &str)> {
+    let number_length = text.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+')).unwrap_or(text.len());
+    let (number, rest) = text.split_at(number_length);
+    Some((number.parse::<f64>().ok()?, rest))
+}
+
+/// RFC 3339 timestamps, or date and time without offset taken as UTC
+fn parse_timestamp(text: &str) -> Option<f64> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+        return Some(timestamp.timestamp_micros() as f64 / 1e6)
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|timestamp| timestamp.and_utc().timestamp_micros() as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::ValueParser;
+
+    fn parse(parser: ValueParser, value: serde_json::Value) -> Option<f64> {
+        parser.parse(&value)
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse(ValueParser::Duration, json!("3d4h12m")), Some(274320.0));
+        assert_eq!(parse(ValueParser::Duration, json!("1.5s")), Some(1.5));
+        assert_eq!(parse(ValueParser::Duration, json!("250ms")), Some(0.25));
+        assert_eq!(parse(ValueParser::Duration, json!("2 hours, 30 minutes")), Some(9000.0));
+        assert_eq!(parse(ValueParser::Duration, json!("01:02:03")), Some(3723.0));
+        assert_eq!(parse(ValueParser::Duration, json!(42)), Some(42.0));
+        assert_eq!(parse(ValueParser::Duration, json!("3 fortnights")), None);
+    }
+
+    #[test]
+    fn parse_byte_sizes() {
+        assert_eq!(parse(ValueParser::Bytes, json!("512MiB")), Some(536870912.0));
+        assert_eq!(parse(ValueParser::Bytes, json!("1.5 GB")), Some(1.5e9));
+        assert_eq!(parse(ValueParser::Bytes, json!("64k")), Some(65536.0));
+        assert_eq!(parse(ValueParser::Bytes, json!("100 bytes")), Some(100.0));
+        assert_eq!(parse(ValueParser::Bytes, json!("12 parsecs")), None);
+    }
+
+    #[test]
+    fn parse_percentages() {
+        assert_eq!(parse(ValueParser::Percent, json!("73%")), Some(0.73));
+        assert_eq!(parse(ValueParser::Percent, json!("50")), Some(0.5));
+        assert_eq!(parse(ValueParser::Percent, json!(25)), Some(0.25));
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(parse(ValueParser::Timestamp, json!("2026-10-17T12:00:00Z")), Some(1792238400.0));
+        assert_eq!(parse(ValueParser::Timestamp, json!("2026-10-17T14:00:00.5+02:00")), Some(1792238400.5));
+        assert_eq!(parse(ValueParser::Timestamp, json!("2026-10-17 12:00:00")), Some(1792238400.0));
+        assert_eq!(parse(ValueParser::Timestamp, json!("2026-10-17")), Some(1792195200.0));
+        assert_eq!(parse(ValueParser::Timestamp, json!("yesterday")), None);
+    }
+
+    #[test]
+    fn parse_age() {
+        let age = parse(ValueParser::Age, json!("2026-10-17T12:00:00Z")).unwrap();
+        assert!(age > 0.0);
+        assert!((parse(ValueParser::Age, json!(1792238400)).unwrap() - age).abs() < 1.0);
+    }
+}